@@ -0,0 +1,91 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use speedy2d::dimen::Vector2;
+use speedy2d::shape::Rectangle;
+
+use block_one::block::Block;
+use block_one::geometry::bounding_box;
+use block_one::graph::Graph;
+
+const SIZES: [usize; 2] = [10_000, 100_000];
+const SPACING: f32 = 150.; // Between the top left corners of two blocks
+
+// Blocks laid out on a square grid
+fn graph(nb_blocks: usize) -> Graph {
+    let columns = (nb_blocks as f32).sqrt().ceil() as usize;
+    let mut graph = Graph::new();
+    for i in 0..nb_blocks {
+        let pos = Vector2::new((i % columns) as f32 * SPACING, (i / columns) as f32 * SPACING);
+        graph.add_block(Block::new_sized(pos, 100., 60.));
+    }
+    graph
+}
+
+// Pseudo random positions over the whole diagram
+fn positions(nb_blocks: usize) -> Vec<Vector2<f32>> {
+    let extent = (nb_blocks as f32).sqrt() * SPACING;
+    (0..1000u32).map(|i| {
+        let hash = i.wrapping_mul(2654435761);
+        Vector2::new((hash % 10007) as f32 / 10007. * extent, (hash / 10007 % 10007) as f32 / 10007. * extent)
+    }).collect()
+}
+
+fn hit_test(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hit_test");
+    for nb_blocks in SIZES {
+        let graph = graph(nb_blocks);
+        let positions = positions(nb_blocks);
+        graph.block_at(Vector2::ZERO); // Build the index
+        group.bench_with_input(BenchmarkId::new("index", nb_blocks), &positions, |b, positions| {
+            b.iter(|| positions.iter().filter_map(|pos| graph.block_at(*pos)).count())
+        });
+        group.bench_with_input(BenchmarkId::new("linear_scan", nb_blocks), &positions, |b, positions| {
+            b.iter(|| positions.iter().filter_map(|pos| graph.blocks().find(|block| block.contains(*pos))).count())
+        });
+    }
+    group.finish();
+}
+
+// Blocks to draw in a 1600x1000 viewport at zoom 1
+fn render_culling(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render_culling");
+    for nb_blocks in SIZES {
+        let graph = graph(nb_blocks);
+        let views: Vec<Rectangle> = positions(nb_blocks).into_iter().take(100).map(|pos| Rectangle::new(pos, pos + Vector2::new(1600., 1000.))).collect();
+        graph.block_at(Vector2::ZERO);
+        group.bench_with_input(BenchmarkId::new("index", nb_blocks), &views, |b, views| {
+            b.iter(|| views.iter().map(|view| graph.blocks_in(view).len()).sum::<usize>())
+        });
+        group.bench_with_input(BenchmarkId::new("linear_scan", nb_blocks), &views, |b, views| {
+            b.iter(|| views.iter().map(|view| graph.blocks().filter(|block| view.intersect(&block.bounds()).is_some()).count()).sum::<usize>())
+        });
+    }
+    group.finish();
+}
+
+// Links to draw in the same viewports, each block linked to the next one in its row
+fn link_culling(c: &mut Criterion) {
+    let mut group = c.benchmark_group("link_culling");
+    for nb_blocks in SIZES {
+        let mut graph = graph(nb_blocks);
+        let columns = (nb_blocks as f32).sqrt().ceil() as u32;
+        for id in 0..nb_blocks as u32 - 1 {
+            if (id + 1) % columns != 0 { graph.add_link(id, 0, id + 1, 0); }
+        }
+        let views: Vec<Rectangle> = positions(nb_blocks).into_iter().take(100).map(|pos| Rectangle::new(pos, pos + Vector2::new(1600., 1000.))).collect();
+        graph.links_in(&views[0]);
+        group.bench_with_input(BenchmarkId::new("index", nb_blocks), &views, |b, views| {
+            b.iter(|| views.iter().map(|view| graph.links_in(view).len()).sum::<usize>())
+        });
+        // Bounds of every link computed from its route each frame
+        group.bench_with_input(BenchmarkId::new("linear_scan", nb_blocks), &views, |b, views| {
+            b.iter(|| views.iter().map(|view| graph.links().filter(|link| {
+                let Some(route) = graph.link_route(link) else { return false; };
+                bounding_box(&route.hull()).is_some_and(|bounds| view.intersect(&bounds).is_some())
+            }).count()).sum::<usize>())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hit_test, render_culling, link_culling);
+criterion_main!(benches);
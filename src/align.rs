@@ -0,0 +1,89 @@
+use speedy2d::dimen::Vector2;
+
+use crate::block::Block;
+use crate::graph::BlockId;
+use crate::resize::blocks_bounds;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Alignment {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    CenterHorizontal, // Same horizontal center, the blocks are stacked on a vertical line
+    CenterVertical, // Same vertical center, the blocks are lined up on a horizontal line
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Distribution {
+    Horizontal,
+    Vertical,
+}
+
+// New position of the blocks, aligned on the bounding box of the blocks
+pub fn align(blocks: &[&Block], alignment: Alignment) -> Vec<(BlockId, Vector2<f32>)> {
+    let Some(bounds) = blocks_bounds(blocks.iter().copied()) else { return vec![]; };
+    let (top_left, bottom_right) = (*bounds.top_left(), *bounds.bottom_right());
+    let center = (top_left + bottom_right) / 2.;
+    blocks.iter().map(|block| {
+        let pos = match alignment {
+            Alignment::Left => Vector2::new(top_left.x, block.pos.y),
+            Alignment::Right => Vector2::new(bottom_right.x - block.width, block.pos.y),
+            Alignment::Top => Vector2::new(block.pos.x, top_left.y),
+            Alignment::Bottom => Vector2::new(block.pos.x, bottom_right.y - block.height),
+            Alignment::CenterHorizontal => Vector2::new(center.x - block.width / 2., block.pos.y),
+            Alignment::CenterVertical => Vector2::new(block.pos.x, center.y - block.height / 2.),
+        };
+        (block.id, pos)
+    }).collect()
+}
+
+// Same space between the blocks, the first and last blocks stay in place
+pub fn distribute(blocks: &[&Block], distribution: Distribution) -> Vec<(BlockId, Vector2<f32>)> {
+    if blocks.len() < 3 { return vec![]; }
+    let is_horizontal = distribution == Distribution::Horizontal;
+    let start = |block: &Block| if is_horizontal { block.pos.x } else { block.pos.y };
+    let size = |block: &Block| if is_horizontal { block.width } else { block.height };
+    let mut blocks = blocks.to_vec();
+    blocks.sort_by(|a, b| start(a).total_cmp(&start(b)));
+    let first = start(blocks[0]);
+    let end = blocks.iter().map(|block| start(block) + size(block)).fold(f32::MIN, f32::max);
+    let total_size: f32 = blocks.iter().map(|block| size(block)).sum();
+    let gap = (end - first - total_size) / (blocks.len() - 1) as f32;
+    let mut position = first;
+    blocks.iter().map(|block| {
+        let pos = if is_horizontal { Vector2::new(position, block.pos.y) } else { Vector2::new(block.pos.x, position) };
+        position += size(block) + gap;
+        (block.id, pos)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::dimen::Vector2;
+
+    use crate::align::{align, Alignment, distribute, Distribution};
+    use crate::block::Block;
+    use crate::test_helpers::sized_block;
+
+    fn block(id: u32, x: f32, y: f32, width: f32) -> Block {
+        sized_block(id, x, y, width, 40.)
+    }
+
+    #[test]
+    fn blocks_are_aligned_on_their_bounds() {
+        let blocks = [block(0, 10., 0., 100.), block(1, 50., 100., 20.)];
+        let blocks: Vec<&Block> = blocks.iter().collect();
+        assert_eq!(align(&blocks, Alignment::Right), vec![(0, Vector2::new(10., 0.)), (1, Vector2::new(90., 100.))]);
+        assert_eq!(align(&blocks, Alignment::CenterHorizontal), vec![(0, Vector2::new(10., 0.)), (1, Vector2::new(50., 100.))]);
+        assert_eq!(align(&blocks, Alignment::Top), vec![(0, Vector2::new(10., 0.)), (1, Vector2::new(50., 0.))]);
+    }
+
+    #[test]
+    fn gaps_are_equal_once_distributed() {
+        let blocks = [block(0, 0., 0., 100.), block(1, 300., 0., 100.), block(2, 110., 0., 50.)];
+        let blocks: Vec<&Block> = blocks.iter().collect();
+        let positions = distribute(&blocks, Distribution::Horizontal);
+        assert_eq!(positions, vec![(0, Vector2::new(0., 0.)), (2, Vector2::new(175., 0.)), (1, Vector2::new(300., 0.))]);
+    }
+}
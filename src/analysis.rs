@@ -0,0 +1,216 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::graph::{BlockId, Graph, LinkId};
+
+// Blocks and links making a part of the graph
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SubGraph {
+    pub blocks: Vec<BlockId>,
+    pub links: Vec<LinkId>,
+}
+
+// Outgoing links of each block as (link, destination)
+fn successors(graph: &Graph) -> HashMap<BlockId, Vec<(LinkId, BlockId)>> {
+    let mut successors: HashMap<BlockId, Vec<(LinkId, BlockId)>> = HashMap::new();
+    for link in graph.links() {
+        successors.entry(link.from).or_default().push((link.id, link.to));
+    }
+    successors
+}
+
+// Kahn's algorithm, fails with the links forming cycles
+pub fn topological_sort(graph: &Graph) -> Result<Vec<BlockId>, Vec<LinkId>> {
+    let successors = successors(graph);
+    let mut in_degrees: HashMap<BlockId, usize> = graph.blocks().map(|block| (block.id, 0)).collect();
+    for link in graph.links() {
+        *in_degrees.entry(link.to).or_default() += 1;
+    }
+    // The blocks are iterated by id so the order is stable
+    let mut queue: VecDeque<BlockId> = graph.blocks().map(|block| block.id).filter(|id| in_degrees[id] == 0).collect();
+    let mut order = vec![];
+    while let Some(id) = queue.pop_front() {
+        order.push(id);
+        for (_, to) in successors.get(&id).into_iter().flatten() {
+            let degree = in_degrees.get_mut(to).unwrap();
+            *degree -= 1;
+            if *degree == 0 { queue.push_back(*to); }
+        }
+    }
+    if order.len() == in_degrees.len() { Ok(order) } else { Err(cycle_links(graph)) }
+}
+
+// Tarjan's algorithm (iterative to handle deep graphs), the components come in reverse topological order
+pub fn strongly_connected_components(graph: &Graph) -> Vec<Vec<BlockId>> {
+    let successors = successors(graph);
+    let mut indices: HashMap<BlockId, usize> = HashMap::new();
+    let mut low_links: HashMap<BlockId, usize> = HashMap::new();
+    let mut stack = vec![];
+    let mut on_stack = HashSet::new();
+    let mut components = vec![];
+    for root in graph.blocks().map(|block| block.id) {
+        if indices.contains_key(&root) { continue; }
+        let mut calls: Vec<(BlockId, usize)> = vec![(root, 0)]; // (block, next successor to visit)
+        while let Some(&(id, next)) = calls.last() {
+            if next == 0 && !indices.contains_key(&id) {
+                indices.insert(id, indices.len());
+                low_links.insert(id, indices[&id]);
+                stack.push(id);
+                on_stack.insert(id);
+            }
+            if let Some((_, to)) = successors.get(&id).and_then(|successors| successors.get(next)) {
+                calls.last_mut().unwrap().1 += 1;
+                if !indices.contains_key(to) {
+                    calls.push((*to, 0));
+                } else if on_stack.contains(to) {
+                    low_links.insert(id, low_links[&id].min(indices[to]));
+                }
+                continue;
+            }
+            calls.pop();
+            if let Some((parent, _)) = calls.last() {
+                low_links.insert(*parent, low_links[parent].min(low_links[&id]));
+            }
+            if low_links[&id] != indices[&id] { continue; }
+            let mut component = vec![];
+            while let Some(member) = stack.pop() {
+                on_stack.remove(&member);
+                component.push(member);
+                if member == id { break; }
+            }
+            component.sort();
+            components.push(component);
+        }
+    }
+    components
+}
+
+// Links belonging to a cycle, self loops included
+pub fn cycle_links(graph: &Graph) -> Vec<LinkId> {
+    let components = strongly_connected_components(graph);
+    let component_of: HashMap<BlockId, usize> = components.iter().enumerate()
+        .flat_map(|(index, component)| component.iter().map(move |id| (*id, index)))
+        .collect();
+    graph.links()
+        .filter(|link| link.from == link.to || component_of.get(&link.from) == component_of.get(&link.to) && components[component_of[&link.from]].len() > 1)
+        .map(|link| link.id)
+        .collect()
+}
+
+pub fn has_cycle(graph: &Graph) -> bool {
+    topological_sort(graph).is_err()
+}
+
+// Blocks reachable by following the links from the `start` blocks, which are included
+pub fn reachable_from(graph: &Graph, start: &[BlockId]) -> SubGraph {
+    let successors = successors(graph);
+    let mut visited: HashSet<BlockId> = start.iter().copied().filter(|id| graph.block(*id).is_some()).collect();
+    let mut queue: VecDeque<BlockId> = visited.iter().copied().collect();
+    let mut links = vec![];
+    while let Some(id) = queue.pop_front() {
+        for (link, to) in successors.get(&id).into_iter().flatten() {
+            links.push(*link);
+            if visited.insert(*to) { queue.push_back(*to); }
+        }
+    }
+    let mut blocks: Vec<BlockId> = visited.into_iter().collect();
+    blocks.sort();
+    links.sort();
+    SubGraph { blocks, links }
+}
+
+// Path with the fewest links following the links direction (breadth first search)
+pub fn shortest_path(graph: &Graph, from: BlockId, to: BlockId) -> Option<SubGraph> {
+    graph.block(from)?;
+    let successors = successors(graph);
+    let mut parents: HashMap<BlockId, (LinkId, BlockId)> = HashMap::new();
+    let mut queue = VecDeque::from([from]);
+    while let Some(id) = queue.pop_front() {
+        if id == to { break; }
+        for (link, next) in successors.get(&id).into_iter().flatten() {
+            if *next == from || parents.contains_key(next) { continue; }
+            parents.insert(*next, (*link, id));
+            queue.push_back(*next);
+        }
+    }
+    if from != to && !parents.contains_key(&to) { return None; }
+    let mut path = SubGraph { blocks: vec![to], links: vec![] };
+    let mut current = to;
+    while current != from {
+        let (link, parent) = parents[&current];
+        path.links.push(link);
+        path.blocks.push(parent);
+        current = parent;
+    }
+    path.blocks.reverse();
+    path.links.reverse();
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{cycle_links, has_cycle, reachable_from, shortest_path, strongly_connected_components, topological_sort};
+    use crate::test_helpers::graph_with_blocks;
+
+    #[test]
+    fn topological_order_follows_links() {
+        let (mut graph, ids) = graph_with_blocks(4);
+        graph.add_link(ids[2], 0, ids[1], 0);
+        graph.add_link(ids[1], 0, ids[0], 0);
+        graph.add_link(ids[3], 0, ids[0], 0);
+        let order = topological_sort(&graph).unwrap();
+        let position = |id| order.iter().position(|other| *other == id).unwrap();
+        assert!(position(ids[2]) < position(ids[1]));
+        assert!(position(ids[1]) < position(ids[0]));
+        assert!(position(ids[3]) < position(ids[0]));
+    }
+
+    #[test]
+    fn cycles_are_detected() {
+        let (mut graph, ids) = graph_with_blocks(4);
+        let a = graph.add_link(ids[0], 0, ids[1], 0).unwrap();
+        let b = graph.add_link(ids[1], 0, ids[2], 0).unwrap();
+        let c = graph.add_link(ids[2], 0, ids[0], 0).unwrap();
+        graph.add_link(ids[2], 0, ids[3], 0);
+        let self_loop = graph.add_link(ids[3], 0, ids[3], 0).unwrap();
+        assert!(has_cycle(&graph));
+        assert_eq!(topological_sort(&graph), Err(vec![a, b, c, self_loop]));
+        assert_eq!(cycle_links(&graph), vec![a, b, c, self_loop]);
+    }
+
+    #[test]
+    fn strongly_connected_components_are_found() {
+        let (mut graph, ids) = graph_with_blocks(5);
+        graph.add_link(ids[0], 0, ids[1], 0);
+        graph.add_link(ids[1], 0, ids[0], 0);
+        graph.add_link(ids[1], 0, ids[2], 0);
+        graph.add_link(ids[2], 0, ids[3], 0);
+        graph.add_link(ids[3], 0, ids[2], 0);
+        let mut components = strongly_connected_components(&graph);
+        components.sort();
+        assert_eq!(components, vec![vec![ids[0], ids[1]], vec![ids[2], ids[3]], vec![ids[4]]]);
+    }
+
+    #[test]
+    fn reachability_follows_the_links_direction() {
+        let (mut graph, ids) = graph_with_blocks(4);
+        let a = graph.add_link(ids[0], 0, ids[1], 0).unwrap();
+        let b = graph.add_link(ids[1], 0, ids[2], 0).unwrap();
+        graph.add_link(ids[3], 0, ids[1], 0);
+        let reachable = reachable_from(&graph, &[ids[0]]);
+        assert_eq!(reachable.blocks, vec![ids[0], ids[1], ids[2]]);
+        assert_eq!(reachable.links, vec![a, b]);
+    }
+
+    #[test]
+    fn shortest_path_has_the_fewest_links() {
+        let (mut graph, ids) = graph_with_blocks(4);
+        graph.add_link(ids[0], 0, ids[1], 0);
+        graph.add_link(ids[1], 0, ids[2], 0);
+        graph.add_link(ids[2], 0, ids[3], 0);
+        let shortcut = graph.add_link(ids[0], 0, ids[3], 0).unwrap();
+        let path = shortest_path(&graph, ids[0], ids[3]).unwrap();
+        assert_eq!(path.blocks, vec![ids[0], ids[3]]);
+        assert_eq!(path.links, vec![shortcut]);
+        assert!(shortest_path(&graph, ids[3], ids[0]).is_none());
+    }
+}
@@ -35,6 +35,7 @@ fn get_easing_fn(f: EasingFunction) -> Box<dyn Fn(f32) -> f32 + 'static> {
     Box::new(function)
 }
 
+#[allow(dead_code)]
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct Animation {
@@ -62,6 +63,7 @@ impl Clone for Animation {
     }
 }
 
+#[allow(dead_code)]
 impl Animation {
     pub fn new(from: f32, to: f32, duration: f32, easing: EasingFunction, es: UserEventSender<AppEvent>) -> Self {
         Self {
@@ -76,7 +78,7 @@ impl Animation {
             is_ended: from == to,
             infinite: false,
             is_reversed: false,
-            speed: (to - from).abs() as f32 / duration,
+            speed: (to - from).abs() / duration,
             last_t: 0.,
             event_sender: es,
         }
@@ -1,15 +1,50 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
 use speedy2d::color::Color;
 use speedy2d::dimen::Vector2;
+use speedy2d::font::FormattedTextBlock;
 use speedy2d::Graphics2D;
+use speedy2d::shape::Rectangle;
 
+use crate::camera::Camera;
+use crate::graph::BlockId;
+use crate::port::{default_ports, Port, PORT_COLOR, PORT_FONT_SIZE, PORT_RADIUS, PORT_SPACING, PortRef, PortSide};
 use crate::render_helper::{draw_rounded_rectangle_with_border};
+use crate::text::{layout_text, TextLine, wrap_label};
+
+pub const TEXT_PADDING: f32 = 8.;
+pub const MIN_WIDTH: f32 = 80.;
+pub const MAX_WIDTH: f32 = 300.;
+pub const MIN_HEIGHT: f32 = 40.;
+const PIN_COLOR: Color = Color::from_rgb(0.85, 0.2, 0.2);
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeMode {
+    Auto, // Fit the label
+    #[default]
+    Fixed,
+}
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Block {
+    pub id: BlockId,
     pub pos: Vector2<f32>,
     pub width: f32,
     pub height: f32,
+    pub size_mode: SizeMode,
     pub is_focused: bool,
+    pub is_pinned: bool, // Not moved by the automatic layouts
+    pub z_index: u32, // Blocks with a higher index are drawn over the others
+    pub title: String,
+    pub body: String,
+    pub inputs: Vec<Port>,
+    pub outputs: Vec<Port>,
+    text_lines: Vec<TextLine>,
+    scaled_text_layout: RefCell<(f32, Vec<Rc<FormattedTextBlock>>)>, // Text layout at the current zoom level
+    port_names_layout: RefCell<(f32, Vec<Rc<FormattedTextBlock>>)>, // Inputs then outputs
 }
 
 impl Default for Block {
@@ -20,15 +55,30 @@ impl Default for Block {
 
 impl Block {
     pub fn new(pos: Vector2<f32>) -> Self {
-        Self::new_sized(pos, 150., 80.)
+        let mut block = Self::new_sized(pos, MIN_WIDTH, MIN_HEIGHT);
+        block.size_mode = SizeMode::Auto;
+        block.inputs = default_ports();
+        block.outputs = default_ports();
+        block
     }
 
     pub fn new_sized(pos: Vector2<f32>, width: f32, height: f32) -> Self {
         Self {
+            id: 0, // Assigned by the graph
             pos,
             width,
             height,
-            is_focused: false
+            size_mode: SizeMode::Fixed,
+            is_focused: false,
+            is_pinned: false,
+            z_index: 0,
+            title: String::new(),
+            body: String::new(),
+            inputs: vec![],
+            outputs: vec![],
+            text_lines: vec![],
+            scaled_text_layout: RefCell::new((1., vec![])),
+            port_names_layout: RefCell::new((1., vec![])),
         }
     }
 
@@ -36,8 +86,140 @@ impl Block {
         self.is_focused = !self.is_focused;
     }
 
-    pub fn render(&self, graphics: &mut Graphics2D) {
+    pub fn contains(&self, pos: Vector2<f32>) -> bool {
+        self.pos.x < pos.x && self.pos.y < pos.y && self.pos.x + self.width > pos.x && self.pos.y + self.height > pos.y
+    }
+
+    pub fn label(&self) -> String {
+        if self.body.is_empty() { self.title.clone() } else { format!("{}\n{}", self.title, self.body) }
+    }
+
+    pub fn set_label(&mut self, title: String, body: String) {
+        self.title = title;
+        self.body = body;
+        self.update_text_layout();
+    }
+
+    pub fn set_size_mode(&mut self, size_mode: SizeMode) {
+        self.size_mode = size_mode;
+        self.update_text_layout();
+    }
+
+    pub fn update_text_layout(&mut self) {
+        self.fit_text(&self.label());
+    }
+
+    // Maximum width of the text, the block is resized to fit it in auto mode
+    pub fn wrap_width(&self) -> f32 {
+        match self.size_mode {
+            SizeMode::Auto => MAX_WIDTH - 2. * TEXT_PADDING,
+            SizeMode::Fixed => self.width - 2. * TEXT_PADDING,
+        }
+    }
+
+    // Layout the text and measure it, also used to preview the text being edited
+    pub fn fit_text(&mut self, text: &str) {
+        self.text_lines = wrap_label(text, self.wrap_width());
+        self.scaled_text_layout.borrow_mut().1.clear();
+        self.port_names_layout.borrow_mut().1.clear();
+        if self.size_mode == SizeMode::Fixed { return; }
+        let text_width = self.text_lines.iter().map(|line| line.layout.width()).fold(0., f32::max);
+        let text_height: f32 = self.text_lines.iter().map(|line| line.height()).sum();
+        self.width = (text_width + 2. * TEXT_PADDING).ceil().clamp(MIN_WIDTH, MAX_WIDTH);
+        let ports_height = (self.inputs.len().max(self.outputs.len()) + 1) as f32 * PORT_SPACING;
+        self.height = (text_height + 2. * TEXT_PADDING).ceil().max(MIN_HEIGHT).max(ports_height);
+    }
+
+    pub fn ports(&self, side: PortSide) -> &Vec<Port> {
+        match side {
+            PortSide::Input => &self.inputs,
+            PortSide::Output => &self.outputs,
+        }
+    }
+
+    pub fn ports_mut(&mut self, side: PortSide) -> &mut Vec<Port> {
+        self.port_names_layout.borrow_mut().1.clear();
+        match side {
+            PortSide::Input => &mut self.inputs,
+            PortSide::Output => &mut self.outputs,
+        }
+    }
+
+    // The ports are evenly spread along the left (inputs) and right (outputs) edges
+    pub fn port_position(&self, port: PortRef) -> Vector2<f32> {
+        let count = self.ports(port.side).len().max(1) as f32;
+        let y = self.height * (port.index as f32 + 1.) / (count + 1.);
+        match port.side {
+            PortSide::Input => self.pos + Vector2::new(0., y),
+            PortSide::Output => self.pos + Vector2::new(self.width, y),
+        }
+    }
+
+    // `radius` is in world units
+    pub fn port_at(&self, pos: Vector2<f32>, radius: f32) -> Option<PortRef> {
+        [PortSide::Output, PortSide::Input].into_iter()
+            .flat_map(|side| (0..self.ports(side).len()).map(move |index| PortRef::new(side, index)))
+            .find(|port| (self.port_position(*port) - pos).magnitude() <= radius)
+    }
+
+    pub fn render_ports(&self, highlight: Option<(PortRef, Color)>, camera: &Camera, graphics: &mut Graphics2D) {
+        let mut port_names_layout = self.port_names_layout.borrow_mut();
+        let show_names = camera.zoom >= 0.5;
+        if show_names && (port_names_layout.0 != camera.zoom || port_names_layout.1.is_empty()) {
+            let names = self.inputs.iter().chain(&self.outputs).map(|port| layout_text(&port.label(), PORT_FONT_SIZE * camera.zoom));
+            *port_names_layout = (camera.zoom, names.collect());
+        }
+        for side in [PortSide::Input, PortSide::Output] {
+            for index in 0..self.ports(side).len() {
+                let port = PortRef::new(side, index);
+                let (color, radius) = match highlight {
+                    Some((highlighted, color)) if highlighted == port => (color, PORT_RADIUS + 1.5),
+                    _ => (PORT_COLOR, PORT_RADIUS),
+                };
+                let pos = camera.to_screen(self.port_position(port));
+                graphics.draw_circle(pos, radius, color);
+                if !show_names { continue; }
+                let Some(name) = port_names_layout.1.get(if side == PortSide::Input { index } else { self.inputs.len() + index }) else { continue; };
+                // The names are drawn outside the block to not overlap its label
+                let offset = match side {
+                    PortSide::Input => Vector2::new(-name.width() - 2. * PORT_RADIUS, -name.height() - PORT_RADIUS),
+                    PortSide::Output => Vector2::new(2. * PORT_RADIUS, -name.height() - PORT_RADIUS),
+                };
+                graphics.draw_text(pos + offset, PORT_COLOR, name);
+            }
+        }
+    }
+
+    pub fn bounds(&self) -> Rectangle {
+        Rectangle::new(self.pos, self.pos + Vector2::new(self.width, self.height))
+    }
+
+    pub fn render(&self, camera: &Camera, graphics: &mut Graphics2D) {
+        self.render_frame(camera, graphics);
+        self.render_label(camera, graphics);
+    }
+
+    pub fn render_frame(&self, camera: &Camera, graphics: &mut Graphics2D) {
         let border_color = if self.is_focused { Color::BLACK } else { Color::from_rgb(100., 100., 100.) };
-        draw_rounded_rectangle_with_border(self.pos.x, self.pos.y, self.width, self.height, 5., 0.5, Color::LIGHT_GRAY, border_color, graphics);
+        let pos = camera.to_screen(self.pos);
+        let radius = (5. * camera.zoom).max(0.5);
+        draw_rounded_rectangle_with_border(pos.x, pos.y, self.width * camera.zoom, self.height * camera.zoom, radius, 0.5, Color::LIGHT_GRAY, border_color, graphics);
+        if self.is_pinned { graphics.draw_circle(pos + Vector2::new(self.width * camera.zoom - 6., 6.), 3., PIN_COLOR); }
     }
-}
\ No newline at end of file
+
+    fn render_label(&self, camera: &Camera, graphics: &mut Graphics2D) {
+        let crop_window = camera.rect_to_screen(&self.bounds());
+        let origin = camera.to_screen(self.pos + Vector2::new(TEXT_PADDING, TEXT_PADDING));
+        let mut scaled_text_layout = self.scaled_text_layout.borrow_mut();
+        let is_scaled = camera.zoom != 1.;
+        if is_scaled && (scaled_text_layout.0 != camera.zoom || scaled_text_layout.1.len() != self.text_lines.len()) {
+            *scaled_text_layout = (camera.zoom, self.text_lines.iter().map(|line| layout_text(&line.text, line.font_size * camera.zoom)).collect());
+        }
+        let mut y = origin.y;
+        for (i, line) in self.text_lines.iter().enumerate() {
+            let layout = if is_scaled { &scaled_text_layout.1[i] } else { &line.layout };
+            graphics.draw_text_cropped(Vector2::new(origin.x, y), crop_window.clone(), Color::BLACK, layout);
+            y += line.height() * camera.zoom;
+        }
+    }
+}
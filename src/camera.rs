@@ -0,0 +1,104 @@
+use speedy2d::dimen::Vector2;
+use speedy2d::shape::Rectangle;
+
+pub const MIN_ZOOM: f32 = 0.1;
+pub const MAX_ZOOM: f32 = 8.;
+const FIT_MARGIN: f32 = 40.; // px
+
+// Maps the world (where the blocks live) to the screen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub offset: Vector2<f32>, // World position of the top left corner of the screen
+    pub zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            offset: Vector2::ZERO,
+            zoom: 1.,
+        }
+    }
+}
+
+impl Camera {
+    #[inline]
+    pub fn to_screen(self, world_pos: Vector2<f32>) -> Vector2<f32> {
+        (world_pos - self.offset) * self.zoom
+    }
+
+    #[inline]
+    pub fn to_world(self, screen_pos: Vector2<f32>) -> Vector2<f32> {
+        screen_pos / self.zoom + self.offset
+    }
+
+    #[inline]
+    pub fn rect_to_screen(&self, rect: &Rectangle) -> Rectangle {
+        Rectangle::new(self.to_screen(*rect.top_left()), self.to_screen(*rect.bottom_right()))
+    }
+
+    pub fn pan(&mut self, screen_delta: Vector2<f32>) {
+        self.offset -= screen_delta / self.zoom;
+    }
+
+    // Zoom keeping the world point under `screen_pos` in place
+    pub fn zoom_at(&mut self, screen_pos: Vector2<f32>, factor: f32) {
+        let world_pos = self.to_world(screen_pos);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.offset = world_pos - screen_pos / self.zoom;
+    }
+
+    pub fn fit(&mut self, bounds: &Rectangle, viewport_size: Vector2<f32>) {
+        let available = viewport_size - Vector2::new(2. * FIT_MARGIN, 2. * FIT_MARGIN);
+        if available.x <= 0. || available.y <= 0. { return; }
+        let zoom = (available.x / bounds.width().max(1.)).min(available.y / bounds.height().max(1.));
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        let center = (*bounds.top_left() + *bounds.bottom_right()) / 2.;
+        self.offset = center - viewport_size / (2. * self.zoom);
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::dimen::Vector2;
+    use speedy2d::shape::Rectangle;
+
+    use crate::camera::{Camera, MAX_ZOOM, MIN_ZOOM};
+
+    fn assert_close(a: Vector2<f32>, b: Vector2<f32>) {
+        assert!((a - b).magnitude() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_cursor() {
+        let mut camera = Camera { offset: Vector2::new(-30., 50.), zoom: 1.5 };
+        let cursor = Vector2::new(120., 80.);
+        let world_pos = camera.to_world(cursor);
+        camera.zoom_at(cursor, 1.7);
+        assert_close(camera.to_world(cursor), world_pos);
+        camera.zoom_at(cursor, 0.2);
+        assert_close(camera.to_world(cursor), world_pos);
+    }
+
+    #[test]
+    fn the_zoom_is_clamped() {
+        let mut camera = Camera::default();
+        camera.zoom_at(Vector2::new(10., 10.), 1000.);
+        assert_eq!(camera.zoom, MAX_ZOOM);
+        camera.zoom_at(Vector2::new(10., 10.), 1e-6);
+        assert_eq!(camera.zoom, MIN_ZOOM);
+    }
+
+    #[test]
+    fn fit_centers_the_bounds() {
+        let mut camera = Camera::default();
+        let viewport = Vector2::new(800., 600.);
+        camera.fit(&Rectangle::new(Vector2::new(1000., 1000.), Vector2::new(1360., 1260.)), viewport);
+        assert_eq!(camera.zoom, 2.); // Limited by the width: (800 - 2 * 40) / 360
+        assert_close(camera.to_screen(Vector2::new(1180., 1130.)), viewport / 2.);
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use speedy2d::dimen::Vector2;
+
+use crate::block::Block;
+use crate::document::Document;
+use crate::graph::{BlockId, Graph};
+use crate::link::Link;
+use crate::resize::blocks_bounds;
+
+// The system clipboard, so that the blocks can be pasted in another window,
+// or an in-process one when there is no system clipboard (e.g. headless)
+pub struct Clipboard {
+    system: Option<arboard::Clipboard>,
+    is_system_checked: bool, // Connected on the first use
+    text: Option<String>, // Last copied text
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self {
+            system: None,
+            is_system_checked: false,
+            text: None,
+        }
+    }
+
+    // Never uses the system clipboard
+    pub fn in_process() -> Self {
+        Self { is_system_checked: true, ..Self::new() }
+    }
+
+    fn system(&mut self) -> Option<&mut arboard::Clipboard> {
+        if !self.is_system_checked {
+            self.is_system_checked = true;
+            self.system = arboard::Clipboard::new().ok();
+        }
+        self.system.as_mut()
+    }
+
+    pub fn set_text(&mut self, text: String) {
+        if let Some(system) = self.system() { let _ = system.set_text(text.as_str()); }
+        self.text = Some(text);
+    }
+
+    // The system clipboard may hold text copied from another application
+    pub fn text(&mut self) -> Option<String> {
+        match self.system().map(|system| system.get_text()) {
+            Some(Ok(text)) => Some(text),
+            _ => self.text.clone(),
+        }
+    }
+}
+
+// The blocks with the links between them, the links to the other blocks are dropped
+pub fn copy(graph: &Graph, ids: &[BlockId]) -> Document {
+    graph.to_document_of(ids)
+}
+
+// Add the content of the document with new ids, its top left corner at the position and above the other blocks
+// The document may come from another application through the system clipboard, it must have been validated
+pub fn paste(graph: &mut Graph, document: &Document, position: Vector2<f32>) -> (Vec<Block>, Vec<Link>) {
+    let pasted = Graph::from_document(document);
+    let Some(bounds) = blocks_bounds(pasted.blocks()) else { return (vec![], vec![]); };
+    let offset = position - *bounds.top_left();
+    let mut ids: HashMap<BlockId, BlockId> = HashMap::new();
+    let mut blocks = vec![];
+    for id in pasted.z_order() {
+        let Some(mut block) = pasted.block(id).cloned() else { continue; };
+        block.pos += offset;
+        block.is_focused = true;
+        let new_id = graph.add_block(block);
+        ids.insert(id, new_id);
+        blocks.extend(graph.block(new_id).cloned());
+    }
+    let mut links = vec![];
+    for link in pasted.links() {
+        let (Some(from), Some(to)) = (ids.get(&link.from).copied(), ids.get(&link.to).copied()) else { continue; };
+        let mut link = link.clone();
+        (link.from, link.to) = (from, to);
+        let Some(id) = graph.add_link_copy(link) else { continue; };
+        links.extend(graph.link(id).cloned());
+    }
+    (blocks, links)
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::dimen::Vector2;
+
+    use crate::block::Block;
+    use crate::clipboard::{Clipboard, copy, paste};
+    use crate::document::Document;
+    use crate::graph::Graph;
+
+    #[test]
+    fn only_the_internal_links_are_pasted() {
+        let mut graph = Graph::new();
+        let a = graph.add_block(Block::new(Vector2::new(0., 0.)));
+        let b = graph.add_block(Block::new(Vector2::new(200., 100.)));
+        let c = graph.add_block(Block::new(Vector2::new(400., 0.)));
+        let styled = graph.add_link(a, 0, b, 0).unwrap();
+        graph.link_mut(styled).unwrap().label = String::from("styled");
+        graph.add_link(b, 0, c, 0);
+        let document = copy(&graph, &[a, b]);
+        let (blocks, links) = paste(&mut graph, &document, Vector2::new(1000., 1000.));
+        assert_eq!(blocks.iter().map(|block| (block.id, block.pos)).collect::<Vec<_>>(), vec![(3, Vector2::new(1000., 1000.)), (4, Vector2::new(1200., 1100.))]);
+        assert_eq!(links.iter().map(|link| (link.id, link.from, link.to)).collect::<Vec<_>>(), vec![(2, 3, 4)]);
+        assert_eq!(graph.link(2).map(|link| link.label.as_str()), Some("styled"));
+        assert_eq!((graph.blocks().count(), graph.links().count()), (5, 3));
+    }
+
+    #[test]
+    fn the_in_process_clipboard_keeps_the_copied_text() {
+        let mut clipboard = Clipboard::in_process();
+        assert_eq!(clipboard.text(), None);
+        let json = Document::default().to_json().unwrap();
+        clipboard.set_text(json.clone());
+        assert_eq!(clipboard.text(), Some(json));
+    }
+}
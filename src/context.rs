@@ -1,128 +1,1035 @@
-use std::cell::RefCell;
-use std::ops::Add;
-use std::rc::Rc;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
+use speedy2d::color::Color;
 use speedy2d::dimen::Vector2;
 use speedy2d::Graphics2D;
-use speedy2d::window::MouseButton;
+use speedy2d::shape::Rectangle;
+use speedy2d::window::{ModifiersState, MouseButton, UserEventSender};
 
-use crate::block::Block;
-use crate::link::Link;
+use crate::align::{align, Alignment, distribute, Distribution};
+use crate::analysis::{cycle_links, reachable_from, shortest_path, strongly_connected_components, SubGraph};
+use crate::block::{Block, SizeMode};
+use crate::camera::Camera;
+use crate::clipboard::{Clipboard, copy, paste};
+use crate::dataflow::Engine;
+use crate::debug::DebugOverlay;
+use crate::document::{Document, DocumentError};
+use crate::force_layout::ForceLayout;
+use crate::graph::{BlockId, Graph, LinkId};
+use crate::grid::Grid;
+use crate::guides::{Guide, GuideIndex, render_guides, SNAP_DISTANCE};
+use crate::history::{Command, History};
+use crate::layout::{BlockMove, layered_layout, MoveAnimation};
+use crate::link::{color_to_hex, HIGHLIGHT_COLOR, HIT_TOLERANCE, LABEL_FONT_SIZE, Link, LINK_COLORS, LINK_WIDTHS, LinkRefusal, LinkRules, next_in};
+use crate::port::{Port, PORT_FONT_SIZE, PORT_HIT_RADIUS, PORT_HOVER_COLOR, PORT_REFUSED_COLOR, PortRef, PortSide};
+use crate::selection::RubberBand;
+use crate::resize::{blocks_bounds, render_handles, render_resize_cursor, Resize, ResizeHandle};
+use crate::render_helper::draw_polyline;
+use crate::routing::{flattening_tolerance, polyline_midpoint, Route, RouteCache};
+use crate::text_editor::{EditTarget, TextEditor};
+use crate::z_order::{LinkLayer, restack, Restack};
+use crate::AppEvent;
+
+const DOUBLE_CLICK_DELAY: Duration = Duration::from_millis(400);
+const CULLING_MARGIN: f32 = 50.; // px
+const DUPLICATE_OFFSET: Vector2<f32> = Vector2::new(20., 20.);
+
+type LinkEndpoints = (BlockId, usize, BlockId, usize); // (from, from_port, to, to_port)
 
 pub struct Context {
-    blocks: Vec<Rc<RefCell<Block>>>,
-    links: Vec<Link>,
+    graph: Graph,
+    history: History,
+    pending_links: Vec<BlockId>, // Source of the links waiting for a destination block
+    connection: Option<(BlockId, PortRef)>, // Port from which a link is being dragged
+    hovered_port: Option<(BlockId, PortRef)>,
+    drag_delta: Vector2<f32>, // World distance covered by the current drag
+    applied_drag_delta: Vector2<f32>, // Distance the dragged blocks have been moved, once snapped
+    drag_anchor: Option<Vector2<f32>>, // Start position of the clicked block, the one snapped to the grid
+    guide_index: Option<(Rectangle, GuideIndex)>, // Start bounds of the dragged blocks and the other blocks
+    guides: Vec<Guide>,
+    is_panning: bool,
+    last_click: Option<(Instant, BlockId)>,
+    resize: Option<Resize>,
+    rubber_band: Option<RubberBand>,
+    click_selects: Option<BlockId>, // Block to select alone if the click doesn't become a drag
+    raise: Option<Command>, // Raise of the clicked blocks, recorded along with the drag
+    hovered_handle: Option<ResizeHandle>,
+    engine: Engine,
+    highlighted_links: HashSet<LinkId>,
+    route_cache: RouteCache,
+    move_animation: Option<MoveAnimation>,
+    force_layout: Option<ForceLayout>, // Running continuously
+    recorded_positions: Vec<(BlockId, Vector2<f32>)>, // Positions of the blocks before the unrecorded moves of the continuous layout
+    clipboard: Clipboard,
+    pub show_results: bool,
+    pub debug: Option<DebugOverlay>, // Shown when set
+    pub editor: Option<TextEditor>,
     pub drag: bool,
-    pub mouse_position: Vector2<f32>,
+    pub mouse_position: Vector2<f32>, // Screen position
+    pub camera: Camera,
+    pub viewport_size: Vector2<f32>,
+    pub space_pressed: bool,
+    pub modifiers: ModifiersState,
+    pub link_rules: LinkRules,
+    pub grid: Grid,
+    pub link_layer: LinkLayer,
+    pub raise_on_click: bool, // Bring the clicked blocks to the front
+    pub path: Option<PathBuf>,
+    pub event_sender: Option<UserEventSender<AppEvent>>, // None when running headless
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Context {
     pub fn new() -> Self {
         Self {
-            blocks: vec![],
-            links: vec![],
+            graph: Graph::new(),
+            history: History::default(),
+            pending_links: vec![],
+            connection: None,
+            hovered_port: None,
+            drag_delta: Vector2::ZERO,
+            applied_drag_delta: Vector2::ZERO,
+            drag_anchor: None,
+            guide_index: None,
+            guides: vec![],
+            is_panning: false,
+            last_click: None,
+            resize: None,
+            rubber_band: None,
+            click_selects: None,
+            raise: None,
+            hovered_handle: None,
+            engine: Engine::new(),
+            highlighted_links: HashSet::new(),
+            route_cache: RouteCache::default(),
+            move_animation: None,
+            force_layout: None,
+            recorded_positions: vec![],
+            clipboard: Clipboard::new(),
+            show_results: false,
+            debug: None,
+            editor: None,
             drag: false,
             mouse_position: Vector2::ZERO,
+            camera: Camera::default(),
+            viewport_size: Vector2::ZERO,
+            space_pressed: false,
+            modifiers: ModifiersState::default(),
+            link_rules: LinkRules::default(),
+            grid: Grid::default(),
+            link_layer: LinkLayer::default(),
+            raise_on_click: false,
+            path: None,
+            event_sender: None,
         }
     }
 
+    pub fn toggle_debug(&mut self) {
+        self.debug = if self.debug.is_some() { None } else { Some(DebugOverlay::new()) };
+    }
+
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history.set_max_depth(depth);
+    }
+
     pub fn on_keydown(&mut self, string: String) {
+        self.finish_moves();
         match string.as_ref() {
             "n" | "a" => self.add_block(),
             "l" => self.add_link(),
-            _ => {dbg!(string);}
+            "f" => self.toggle_size_mode(),
+            "i" => self.add_port(PortSide::Input),
+            "o" => self.add_port(PortSide::Output),
+            "I" => self.remove_port(PortSide::Input),
+            "O" => self.remove_port(PortSide::Output),
+            "0" => self.camera.reset(),
+            "1" => self.zoom_to_fit(),
+            "e" => self.show_results = !self.show_results,
+            "c" => self.highlight_cycles(),
+            "k" => self.select_components(),
+            "r" => self.select_reachable(),
+            "p" => self.select_shortest_path(),
+            "g" => self.grid.toggle_snap(),
+            "G" => self.grid.next_style(),
+            "+" | "=" => self.grid.next_size(),
+            "-" => self.grid.previous_size(),
+            "L" => self.layered_layout(),
+            "F" => self.force_layout(),
+            "R" => self.toggle_continuous_force_layout(),
+            "P" => self.toggle_pin(),
+            "s" => self.restyle_links(|first, link| link.style = first.style.next()),
+            "h" => self.restyle_links(|first, link| link.end_arrow = first.end_arrow.next()),
+            "H" => self.restyle_links(|first, link| link.start_arrow = first.start_arrow.next()),
+            "d" => self.restyle_links(|first, link| link.dash = first.dash.next()),
+            "w" => self.restyle_links(|first, link| link.width = next_in(&LINK_WIDTHS, first.width)),
+            "C" => self.restyle_links(|first, link| link.color = Color::from_hex_rgb(next_in(&LINK_COLORS, color_to_hex(first.color)))),
+            "t" => self.edit_label(),
+            "]" => self.restack(Restack::Forward),
+            "[" => self.restack(Restack::Backward),
+            "}" => self.restack(Restack::ToFront),
+            "{" => self.restack(Restack::ToBack),
+            "z" => self.link_layer = self.link_layer.toggle(),
+            _ => {}
         }
     }
 
+    #[inline]
+    pub fn mouse_world_position(&self) -> Vector2<f32> {
+        self.camera.to_world(self.mouse_position)
+    }
+
     pub fn on_mouse_clicked(&mut self, button: MouseButton) {
-        match button {
-            MouseButton::Left => {
-                self.blocks.iter().for_each(|block| block.borrow_mut().is_focused = false); // TODO: check if shift is pressed
-                let clicked_block = self.get_block_at(self.mouse_position);
-                if clicked_block.is_none() { return; }
-                let block = Rc::clone(clicked_block.as_ref().unwrap());
-                block.borrow_mut().toggle_focus();
-                drop(clicked_block);
-                let last_link = self.links.last_mut();
-                if let Some(link) = last_link {
-                    if link.to.is_none() {
-                        link.to(block.clone());
-                    }
+        if button == MouseButton::Middle || button == MouseButton::Left && self.space_pressed {
+            self.is_panning = true;
+            return;
+        }
+        self.finish_moves();
+        if button == MouseButton::Right {
+            self.cancel_pending_links();
+            return;
+        }
+        if button == MouseButton::Left {
+            let clicked_block = self.graph.block_at(self.mouse_world_position());
+            if self.editor.is_some() {
+                if clicked_block.map(EditTarget::Block) == self.editor.as_ref().map(|editor| editor.target) { return; }
+                self.commit_edition();
+            }
+            if let Some(handle) = self.handle_at(self.mouse_position) {
+                let blocks = self.graph.focused_blocks().iter().filter_map(|id| self.graph.block(*id)).cloned().collect();
+                self.resize = Resize::new(handle, self.mouse_world_position(), blocks);
+                return;
+            }
+            let is_additive = self.modifiers.shift() || self.modifiers.ctrl() || self.modifiers.logo();
+            if self.pending_links.is_empty() {
+                if let Some(port) = self.graph.port_at(self.mouse_world_position(), PORT_HIT_RADIUS / self.camera.zoom) {
+                    self.connection = Some(port);
+                    return;
+                }
+                // The links under the blocks are hidden by them
+                let is_link_hidden = self.link_layer == LinkLayer::Below && clicked_block.is_some();
+                let clicked_link = if is_link_hidden { None } else { self.graph.link_at(self.mouse_world_position(), HIT_TOLERANCE / self.camera.zoom) };
+                if let Some(clicked_link) = clicked_link {
+                    if !is_additive { self.clear_selection(); }
+                    if let Some(link) = self.graph.link_mut(clicked_link) { link.is_selected = !is_additive || !link.is_selected; }
+                    return;
                 }
             }
-            _ => {}
+            let Some(clicked_block) = clicked_block else {
+                if !is_additive { self.clear_selection(); }
+                self.rubber_band = Some(RubberBand::new(self.mouse_world_position(), self.graph.focused_blocks(), self.graph.selected_links()));
+                return;
+            };
+            if let Some((time, block)) = self.last_click {
+                if block == clicked_block && time.elapsed() < DOUBLE_CLICK_DELAY {
+                    self.last_click = None;
+                    self.start_edition(clicked_block);
+                    return;
+                }
+            }
+            self.last_click = Some((Instant::now(), clicked_block));
+            self.drag_anchor = self.graph.block(clicked_block).map(|block| block.pos);
+            let is_focused = self.graph.block(clicked_block).is_some_and(|block| block.is_focused);
+            if is_additive {
+                if let Some(block) = self.graph.block_mut(clicked_block) { block.toggle_focus(); }
+            } else if is_focused {
+                self.click_selects = Some(clicked_block); // Keep the selection to drag it
+            } else {
+                self.clear_selection();
+                if let Some(block) = self.graph.block_mut(clicked_block) { block.is_focused = true; }
+            }
+            if self.raise_on_click {
+                self.record_drag(None);
+                self.raise = self.restack_blocks(Restack::ToFront);
+            }
+            let mut links: Vec<Link> = vec![];
+            for from in std::mem::take(&mut self.pending_links) {
+                // The pending links leave from the first output to the first compatible input
+                let Some(to_port) = self.compatible_input(from, 0, clicked_block) else { continue; };
+                let Some(id) = self.graph.add_link(from, 0, clicked_block, to_port) else { continue; };
+                links.extend(self.graph.link(id).cloned());
+            }
+            if !links.is_empty() { self.history.push(Command::AddLinks(links)); }
         }
     }
 
     fn add_block(&mut self) {
-        let block = Block::new(self.mouse_position);
-        self.blocks.push(Rc::new(RefCell::new(block)));
+        let id = self.graph.add_block(Block::new(self.grid.snap_point(self.mouse_world_position())));
+        self.history.push(Command::AddBlock(self.graph.block(id).unwrap().clone()));
     }
 
     fn add_link(&mut self) {
-        let focused_block = self.get_focused_blocks();
-        for block in focused_block {
-            self.links.push(Link::new(block));
+        self.pending_links = self.graph.focused_blocks();
+    }
+
+    fn compatible_input(&self, from: BlockId, from_port: usize, to: BlockId) -> Option<usize> {
+        let nb_inputs = self.graph.block(to)?.inputs.len();
+        (0..nb_inputs).find(|to_port| self.link_rules.check(&self.graph, from, from_port, to, *to_port).is_ok())
+    }
+
+    // Add an untyped port to the focused blocks
+    fn add_port(&mut self, side: PortSide) {
+        let mut before = vec![];
+        let mut after = vec![];
+        for id in self.graph.focused_blocks() {
+            let Some(block) = self.graph.block_mut(id) else { continue; };
+            before.push(block.clone());
+            let ports = block.ports_mut(side);
+            let prefix = if side == PortSide::Input { "in" } else { "out" };
+            let name = format!("{}{}", prefix, ports.len());
+            ports.push(Port { name, ..Port::any() });
+            block.update_text_layout();
+            after.push(block.clone());
         }
+        if !after.is_empty() { self.history.push(Command::UpdateBlocks { before, after }); }
+    }
+
+    // Remove the last port of the focused blocks with the links connected to it
+    fn remove_port(&mut self, side: PortSide) {
+        let mut before = vec![];
+        let mut after = vec![];
+        let mut links = vec![];
+        for id in self.graph.focused_blocks() {
+            let Some(block) = self.graph.block(id) else { continue; };
+            let Some(index) = block.ports(side).len().checked_sub(1) else { continue; };
+            let connected: Vec<LinkId> = self.graph.links()
+                .filter(|link| link.is_connected_to(id, PortRef::new(side, index)))
+                .map(|link| link.id)
+                .collect();
+            links.extend(connected.iter().filter_map(|link| self.graph.remove_link(*link)));
+            let Some(block) = self.graph.block_mut(id) else { continue; };
+            before.push(block.clone());
+            block.ports_mut(side).pop();
+            block.update_text_layout();
+            after.push(block.clone());
+        }
+        if after.is_empty() { return; }
+        self.history.push(Command::Batch(vec![
+            Command::RemoveBlocks { blocks: vec![], links },
+            Command::UpdateBlocks { before, after },
+        ]));
+    }
+
+    // Rename and retype a port, removing the links which no longer fit its type
+    fn update_port(&mut self, id: BlockId, port: PortRef, label: &str) {
+        let new_port = Port::from_label(label);
+        let Some(block) = self.graph.block_mut(id) else { return; };
+        let before = block.clone();
+        let Some(edited) = block.ports_mut(port.side).get_mut(port.index) else { return; };
+        if *edited == new_port { return; }
+        *edited = new_port;
+        block.update_text_layout();
+        let after = block.clone();
+        let incompatible: Vec<LinkId> = self.graph.links()
+            .filter(|link| link.is_connected_to(id, port))
+            .filter(|link| {
+                let output = self.graph.block(link.from).and_then(|block| block.outputs.get(link.from_port));
+                let input = self.graph.block(link.to).and_then(|block| block.inputs.get(link.to_port));
+                matches!((output, input), (Some(output), Some(input)) if !output.is_compatible_with(input))
+            })
+            .map(|link| link.id)
+            .collect();
+        let links = incompatible.iter().filter_map(|link| self.graph.remove_link(*link)).collect();
+        self.history.push(Command::Batch(vec![
+            Command::RemoveBlocks { blocks: vec![], links },
+            Command::UpdateBlocks { before: vec![before], after: vec![after] },
+        ]));
+    }
+
+    pub fn cancel_pending_links(&mut self) -> bool {
+        let has_pending_links = !self.pending_links.is_empty();
+        self.pending_links.clear();
+        has_pending_links
+    }
+
+    // Escape cancels the pending links first, then clears the selection and the highlights
+    pub fn on_escape(&mut self) {
+        if self.cancel_pending_links() { return; }
+        self.clear_selection();
+        self.highlighted_links.clear();
+    }
+
+    // Lay out the selection, or the whole graph when less than two blocks are selected
+    fn layered_layout(&mut self) {
+        let mut ids = self.graph.focused_blocks();
+        if ids.len() < 2 { ids = self.graph.blocks().map(|block| block.id).collect(); }
+        let moves = layered_layout(&self.graph, &ids).into_iter()
+            .filter_map(|(id, pos)| self.graph.block(id).map(|block| (id, block.pos, pos)))
+            .collect();
+        self.animate_moves(moves);
+    }
+
+    pub fn align(&mut self, alignment: Alignment) {
+        let ids = self.graph.focused_blocks();
+        let blocks: Vec<&Block> = ids.iter().filter_map(|id| self.graph.block(*id)).collect();
+        if blocks.len() < 2 { return; }
+        let moves = align(&blocks, alignment).into_iter()
+            .filter_map(|(id, pos)| self.graph.block(id).map(|block| (id, block.pos, pos)))
+            .collect();
+        self.animate_moves(moves);
+    }
+
+    pub fn distribute(&mut self, distribution: Distribution) {
+        let ids = self.graph.focused_blocks();
+        let blocks: Vec<&Block> = ids.iter().filter_map(|id| self.graph.block(*id)).collect();
+        let moves = distribute(&blocks, distribution).into_iter()
+            .filter_map(|(id, pos)| self.graph.block(id).map(|block| (id, block.pos, pos)))
+            .collect();
+        self.animate_moves(moves);
+    }
+
+    // Change the z-order of the focused blocks
+    pub fn restack(&mut self, change: Restack) {
+        let Some(command) = self.restack_blocks(change) else { return; };
+        self.history.push(command);
     }
 
+    // Only the raised blocks change when brought to the front, otherwise the z-indices of all the blocks are renumbered from 0
+    fn restack_blocks(&mut self, change: Restack) -> Option<Command> {
+        let ids = self.graph.focused_blocks();
+        if ids.is_empty() { return None; }
+        if change == Restack::ToFront {
+            let before: Vec<Block> = ids.iter().filter_map(|id| self.graph.block(*id)).cloned().collect();
+            if !self.graph.bring_to_front(&ids) { return None; }
+            let after = ids.iter().filter_map(|id| self.graph.block(*id)).cloned().collect();
+            return Some(Command::UpdateBlocks { before, after });
+        }
+        let selected: HashSet<BlockId> = ids.into_iter().collect();
+        let order = self.graph.z_order();
+        let new_order = restack(&order, &selected, change);
+        if new_order == order { return None; }
+        let mut before = vec![];
+        let mut after = vec![];
+        for (z_index, id) in new_order.into_iter().enumerate() {
+            let Some(block) = self.graph.block_mut(id) else { continue; };
+            if block.z_index == z_index as u32 { continue; }
+            before.push(block.clone());
+            block.z_index = z_index as u32;
+            after.push(block.clone());
+        }
+        if after.is_empty() { return None; }
+        Some(Command::UpdateBlocks { before, after })
+    }
+
+    fn force_layout(&mut self) {
+        let moves = ForceLayout::settle(&self.graph).into_iter()
+            .filter_map(|(id, pos)| self.graph.block(id).map(|block| (id, block.pos, pos)))
+            .collect();
+        self.animate_moves(moves);
+    }
+
+    fn toggle_continuous_force_layout(&mut self) {
+        self.finish_moves();
+        if self.force_layout.take().is_some() { return; }
+        self.force_layout = Some(ForceLayout::new());
+        self.recorded_positions = self.block_positions();
+    }
+
+    // The moves recorded by another command must not be recorded again by the continuous layout
+    fn sync_recorded_positions(&mut self) {
+        if self.force_layout.is_some() { self.recorded_positions = self.block_positions(); }
+    }
+
+    fn block_positions(&self) -> Vec<(BlockId, Vector2<f32>)> {
+        self.graph.blocks().map(|block| (block.id, block.pos)).collect()
+    }
+
+    fn toggle_pin(&mut self) {
+        let ids = self.graph.focused_blocks();
+        let is_pinned = ids.iter().filter_map(|id| self.graph.block(*id)).all(|block| block.is_pinned);
+        let mut before = vec![];
+        let mut after = vec![];
+        for id in ids {
+            let Some(block) = self.graph.block_mut(id) else { continue; };
+            before.push(block.clone());
+            block.is_pinned = !is_pinned;
+            after.push(block.clone());
+        }
+        if !after.is_empty() { self.history.push(Command::UpdateBlocks { before, after }); }
+    }
+
+    // The selected links all take the value following the one of the first selected link, e.g. its next style
+    fn restyle_links(&mut self, next: impl Fn(&Link, &mut Link)) {
+        let ids = self.graph.selected_links();
+        let Some(first) = ids.first().and_then(|id| self.graph.link(*id)).cloned() else { return; };
+        let mut before = vec![];
+        let mut after = vec![];
+        for id in ids {
+            let Some(link) = self.graph.link_mut(id) else { continue; };
+            before.push(link.clone());
+            next(&first, link);
+            after.push(link.clone());
+        }
+        self.history.push(Command::UpdateLinks { before, after });
+    }
+
+    // Edit the port under the cursor, or else the label of the single selected link
+    fn edit_label(&mut self) {
+        if let Some((id, port)) = self.hovered_port {
+            let Some(block) = self.graph.block(id) else { return; };
+            self.editor = Some(TextEditor::for_port(block, port));
+            return;
+        }
+        let [id] = self.graph.selected_links()[..] else { return; };
+        let Some(link) = self.graph.link(id) else { return; };
+        self.editor = Some(TextEditor::for_link(link));
+    }
+
+    // Record the moves as a single command and animate the blocks to their new position
+    fn animate_moves(&mut self, moves: Vec<BlockMove>) {
+        self.finish_moves();
+        let moves: Vec<BlockMove> = moves.into_iter().filter(|(_, from, to)| from != to).collect();
+        if moves.is_empty() { return; }
+        self.history.push(Command::PlaceBlocks(moves.clone()));
+        match self.event_sender.clone() {
+            Some(event_sender) => self.move_animation = Some(MoveAnimation::new(moves, event_sender)),
+            None => Command::PlaceBlocks(moves).apply(&mut self.graph),
+        }
+    }
+
+    // Jump to the end of the running animation and record the moves of the continuous layout,
+    // the graph must match the history before being edited
+    fn finish_moves(&mut self) {
+        if let Some(animation) = self.move_animation.take() { animation.finish(&mut self.graph); }
+        if self.force_layout.is_none() { return; }
+        let moves: Vec<BlockMove> = self.recorded_positions.iter()
+            .filter_map(|(id, before)| self.graph.block(*id).map(|block| (*id, *before, block.pos)))
+            .filter(|(_, before, after)| before != after)
+            .collect();
+        self.recorded_positions = self.block_positions();
+        if !moves.is_empty() { self.history.push(Command::PlaceBlocks(moves)); }
+    }
+
+    fn highlight_cycles(&mut self) {
+        self.highlighted_links = cycle_links(&self.graph).into_iter().collect();
+    }
+
+    fn select(&mut self, selection: SubGraph) {
+        self.clear_selection();
+        for id in selection.blocks {
+            if let Some(block) = self.graph.block_mut(id) { block.is_focused = true; }
+        }
+        for id in selection.links {
+            if let Some(link) = self.graph.link_mut(id) { link.is_selected = true; }
+        }
+    }
+
+    // Extend the selection to the strongly connected components of the focused blocks
+    fn select_components(&mut self) {
+        let focused_blocks: HashSet<BlockId> = self.graph.focused_blocks().into_iter().collect();
+        if focused_blocks.is_empty() { return; }
+        let blocks: HashSet<BlockId> = strongly_connected_components(&self.graph).into_iter()
+            .filter(|component| component.iter().any(|id| focused_blocks.contains(id)))
+            .flatten()
+            .collect();
+        let links = self.graph.links()
+            .filter(|link| blocks.contains(&link.from) && blocks.contains(&link.to))
+            .map(|link| link.id)
+            .collect();
+        self.select(SubGraph { blocks: blocks.into_iter().collect(), links });
+    }
+
+    fn select_reachable(&mut self) {
+        let focused_blocks = self.graph.focused_blocks();
+        if focused_blocks.is_empty() { return; }
+        self.select(reachable_from(&self.graph, &focused_blocks));
+    }
+
+    // The path can go from the first selected block to the second one or the other way round
+    fn select_shortest_path(&mut self) {
+        let [a, b] = self.graph.focused_blocks()[..] else { return; };
+        let Some(path) = shortest_path(&self.graph, a, b).or_else(|| shortest_path(&self.graph, b, a)) else { return; };
+        self.select(path);
+    }
+
+    pub fn on_mouse_move(&mut self, position: Vector2<f32>) {
+        if self.is_panning {
+            self.camera.pan(position - self.mouse_position);
+        } else if self.resize.is_some() {
+            self.update_resize(self.camera.to_world(position));
+        } else if self.connection.is_some() {
+            // The link preview follows the cursor
+        } else if let Some(rubber_band) = &mut self.rubber_band {
+            rubber_band.corner = self.camera.to_world(position);
+            self.update_rubber_band_selection();
+        } else if self.drag {
+            self.move_block(position);
+        }
+        self.mouse_position = position;
+        self.hovered_handle = self.handle_at(position);
+        self.hovered_port = self.graph.port_at(self.mouse_world_position(), PORT_HIT_RADIUS / self.camera.zoom);
+    }
+
+    // The dragged blocks snap to the alignment guides, or else the clicked block is snapped to the grid
     pub fn move_block(&mut self, new_position: Vector2<f32>) {
-        let delta = new_position - self.mouse_position;
-        self.get_focused_blocks().iter().for_each(|block| {
-            let old_pos = block.borrow_mut().pos.clone();
-            block.borrow_mut().pos= old_pos.add(delta);
-        });
-    }
-
-    pub fn delete_focused_block(&mut self) {
-        let focused_blocks = self.get_focused_blocks();
-        let mut block_remove_indices = vec![];
-        let mut link_remove_indices = vec![];
-        for block in focused_blocks.iter() {
-            let index = self.blocks.iter().position(|context_block|Rc::ptr_eq(block, context_block));
-            if let Some(i) = index { block_remove_indices.push(i); }
-            for (i, link) in self.links.iter().enumerate() {
-                if Rc::ptr_eq(&link.from, block) { link_remove_indices.push(i); }
-                if let Some(to) = &link.to {
-                    if Rc::ptr_eq(&to, block) { link_remove_indices.push(i); }
-                }
+        self.drag_delta += (new_position - self.mouse_position) / self.camera.zoom;
+        let mut applied_delta = match self.drag_anchor {
+            Some(anchor) => self.grid.snap_point(anchor + self.drag_delta) - anchor,
+            None => self.drag_delta,
+        };
+        self.guides.clear();
+        if self.guide_index.is_none() { self.guide_index = self.build_guide_index(); }
+        if let Some((bounds, index)) = self.guide_index.as_ref().filter(|_| !self.modifiers.alt()) {
+            let moving = Rectangle::new(*bounds.top_left() + self.drag_delta, *bounds.bottom_right() + self.drag_delta);
+            let (offset, guides) = index.snap(&moving, SNAP_DISTANCE / self.camera.zoom);
+            if let Some(offset) = offset[0] { applied_delta.x = self.drag_delta.x + offset; }
+            if let Some(offset) = offset[1] { applied_delta.y = self.drag_delta.y + offset; }
+            self.guides = guides;
+        }
+        let delta = applied_delta - self.applied_drag_delta;
+        self.applied_drag_delta = applied_delta;
+        for id in self.graph.focused_blocks() {
+            if let Some(block) = self.graph.block_mut(id) { block.pos += delta; }
+        }
+    }
+
+    fn build_guide_index(&self) -> Option<(Rectangle, GuideIndex)> {
+        let bounds = self.focused_bounds()?;
+        let rects = self.graph.blocks().filter(|block| !block.is_focused).map(|block| block.bounds()).collect();
+        Some((bounds, GuideIndex::new(rects)))
+    }
+
+    fn focused_bounds(&self) -> Option<Rectangle> {
+        blocks_bounds(self.graph.blocks().filter(|block| block.is_focused))
+    }
+
+    // `pos` is a screen position, the handles have a constant size on the screen
+    fn handle_at(&self, pos: Vector2<f32>) -> Option<ResizeHandle> {
+        if self.editor.is_some() { return None; }
+        ResizeHandle::at(&self.camera.rect_to_screen(&self.focused_bounds()?), pos)
+    }
+
+    // The handle to display as the cursor, if any
+    pub fn cursor_handle(&self) -> Option<ResizeHandle> {
+        self.resize.as_ref().map(|resize| resize.handle).or(self.hovered_handle)
+    }
+
+    fn update_resize(&mut self, mouse_position: Vector2<f32>) {
+        let Some(resize) = &self.resize else { return; };
+        let bounds = resize.resized_bounds(resize.snap_mouse(mouse_position, &self.grid), self.modifiers.shift());
+        for mut block in resize.apply(&bounds) {
+            block.size_mode = SizeMode::Fixed;
+            block.update_text_layout();
+            self.graph.insert_block(block);
+        }
+    }
+
+    fn end_resize(&mut self) {
+        let Some(resize) = self.resize.take() else { return; };
+        let after: Vec<Block> = resize.ids().iter().filter_map(|id| self.graph.block(*id)).cloned().collect();
+        let is_resized = resize.before.iter().zip(&after).any(|(before, after)| before.width != after.width || before.height != after.height);
+        if is_resized { self.history.push(Command::UpdateBlocks { before: resize.before, after }); }
+        self.sync_recorded_positions();
+    }
+
+    fn update_rubber_band_selection(&mut self) {
+        let Some(rubber_band) = &self.rubber_band else { return; };
+        let band = rubber_band.rect();
+        let mut blocks: HashSet<BlockId> = rubber_band.base_blocks.iter().copied().collect();
+        blocks.extend(self.graph.blocks_in(&band).into_iter().filter(|id| self.graph.block(*id).is_some_and(|block| rubber_band.selects_rect(&block.bounds()))));
+        let mut links: HashSet<LinkId> = rubber_band.base_links.iter().copied().collect();
+        links.extend(self.graph.links_in(&band).into_iter().filter(|id| {
+            let Some(route) = self.graph.link(*id).and_then(|link| self.graph.link_route(link)) else { return false; };
+            rubber_band.selects_polyline(&route.points(20))
+        }));
+        self.graph.set_focus(|block| blocks.contains(&block.id));
+        self.graph.set_link_selection(|link| links.contains(&link.id));
+    }
+
+    pub fn select_all(&mut self) {
+        self.graph.set_focus(|_| true);
+        self.graph.set_link_selection(|_| true);
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.graph.set_focus(|_| false);
+        self.graph.set_link_selection(|_| false);
+    }
+
+    // The link that would be created by dropping the dragged port at the cursor
+    // Dropped on a block, it connects to the first compatible port of the opposite side
+    fn connection_target(&self) -> Option<(LinkEndpoints, Result<(), LinkRefusal>)> {
+        let (source, port) = self.connection?;
+        let pos = self.mouse_world_position();
+        let (target, index) = match self.graph.port_at(pos, PORT_HIT_RADIUS / self.camera.zoom) {
+            Some((target, target_port)) if target_port.side != port.side => (target, target_port.index),
+            Some(_) => return None,
+            None => {
+                let target = self.graph.block_at(pos)?;
+                let (from, to) = if port.side == PortSide::Output { (source, target) } else { (target, source) };
+                let nb_ports = self.graph.block(target)?.ports(if port.side == PortSide::Output { PortSide::Input } else { PortSide::Output }).len();
+                let index = (0..nb_ports)
+                    .find(|index| {
+                        let (from_port, to_port) = if port.side == PortSide::Output { (port.index, *index) } else { (*index, port.index) };
+                        self.link_rules.check(&self.graph, from, from_port, to, to_port).is_ok()
+                    })
+                    .unwrap_or(0);
+                (target, index)
             }
+        };
+        let endpoints = match port.side {
+            PortSide::Output => (source, port.index, target, index),
+            PortSide::Input => (target, index, source, port.index),
+        };
+        let (from, from_port, to, to_port) = endpoints;
+        Some((endpoints, self.link_rules.check(&self.graph, from, from_port, to, to_port)))
+    }
+
+    // Connect the dragged port to the port under the cursor, dropping it elsewhere cancels the link
+    fn end_connection(&mut self) {
+        let target = self.connection_target();
+        self.connection = None;
+        let Some(((from, from_port, to, to_port), Ok(()))) = target else { return; };
+        let Some(id) = self.graph.add_link(from, from_port, to, to_port) else { return; };
+        let link = self.graph.link(id).unwrap().clone();
+        self.history.push(Command::AddLinks(vec![link]));
+    }
+
+    // The whole drag is recorded as a single move
+    pub fn end_drag(&mut self) {
+        self.is_panning = false;
+        self.rubber_band = None;
+        self.end_connection();
+        self.end_resize();
+        if !self.drag { return self.record_drag(None); }
+        self.drag = false;
+        let delta = self.applied_drag_delta;
+        let has_moved = self.drag_delta != Vector2::ZERO;
+        self.drag_delta = Vector2::ZERO;
+        self.applied_drag_delta = Vector2::ZERO;
+        self.drag_anchor = None;
+        self.guide_index = None;
+        self.guides.clear();
+        if let Some(id) = self.click_selects.take() {
+            if !has_moved {
+                self.clear_selection();
+                if let Some(block) = self.graph.block_mut(id) { block.is_focused = true; }
+            }
+        }
+        let ids = self.graph.focused_blocks();
+        if ids.is_empty() || delta == Vector2::ZERO { return self.record_drag(None); }
+        if self.force_layout.is_some() {
+            self.record_drag(None);
+            return self.finish_moves(); // Recorded along with the moves of the layout
+        }
+        self.record_drag(Some(Command::MoveBlocks { ids, delta }));
+    }
+
+    // The raise of the clicked blocks is undone along with the drag that follows it
+    fn record_drag(&mut self, command: Option<Command>) {
+        let mut commands: Vec<Command> = self.raise.take().into_iter().chain(command).collect();
+        match commands.len() {
+            0 => {}
+            1 => self.history.push(commands.remove(0)),
+            _ => self.history.push(Command::Batch(commands)),
+        }
+    }
+
+    // Scroll lines zoom the view, trackpad scrolls pan it unless it's a pinch (sent with ctrl)
+    pub fn on_scroll(&mut self, delta: Vector2<f32>, is_line_scroll: bool) {
+        if is_line_scroll || self.modifiers.ctrl() {
+            let step = if is_line_scroll { delta.y * 0.1 } else { delta.y * 0.01 };
+            self.camera.zoom_at(self.mouse_position, 1. + step.clamp(-0.5, 0.5));
+        } else {
+            self.camera.pan(delta);
+        }
+    }
+
+    fn zoom_to_fit(&mut self) {
+        let Some(bounds) = blocks_bounds(self.graph.blocks()) else { return self.camera.reset(); };
+        self.camera.fit(&bounds, self.viewport_size);
+    }
+
+    // Delete the focused blocks with their links and the selected links
+    pub fn delete_selection(&mut self) {
+        self.remove(&self.graph.focused_blocks(), &self.graph.selected_links());
+    }
+
+    // The blocks are removed with all their links
+    fn remove(&mut self, block_ids: &[BlockId], link_ids: &[LinkId]) {
+        self.finish_moves();
+        if block_ids.is_empty() && link_ids.is_empty() { return; }
+        self.pending_links.retain(|id| !block_ids.contains(id));
+        let (blocks, mut links) = self.graph.remove_blocks(block_ids);
+        links.extend(link_ids.iter().filter_map(|id| self.graph.remove_link(*id)));
+        self.history.push(Command::RemoveBlocks { blocks, links });
+    }
+
+    pub fn copy_selection(&mut self) {
+        if self.is_editing() { return; }
+        let ids = self.graph.focused_blocks();
+        if ids.is_empty() { return; }
+        let Ok(json) = copy(&self.graph, &ids).to_json() else { return; };
+        self.clipboard.set_text(json);
+    }
+
+    // Only what has been copied is removed, the selected links between other blocks stay
+    pub fn cut_selection(&mut self) {
+        let ids = self.graph.focused_blocks();
+        if self.is_editing() || ids.is_empty() { return; }
+        self.copy_selection();
+        self.remove(&ids, &[]);
+    }
+
+    // Paste at the mouse position, the clipboard is ignored if it doesn't hold blocks
+    pub fn paste(&mut self) {
+        if self.is_editing() { return; }
+        let Some(document) = self.clipboard.text().and_then(|text| Document::from_json(&text).ok()) else { return; };
+        self.paste_document(&document, self.mouse_world_position());
+    }
+
+    pub fn duplicate_selection(&mut self) {
+        if self.is_editing() { return; }
+        let ids = self.graph.focused_blocks();
+        let Some(bounds) = blocks_bounds(ids.iter().filter_map(|id| self.graph.block(*id))) else { return; };
+        let document = copy(&self.graph, &ids);
+        self.paste_document(&document, *bounds.top_left() + DUPLICATE_OFFSET);
+    }
+
+    // The pasted blocks become the selection
+    fn paste_document(&mut self, document: &Document, position: Vector2<f32>) {
+        self.finish_moves();
+        let (blocks, links) = paste(&mut self.graph, document, position);
+        if blocks.is_empty() { return; }
+        let pasted: HashSet<BlockId> = blocks.iter().map(|block| block.id).collect();
+        self.graph.set_focus(|block| pasted.contains(&block.id));
+        self.graph.set_link_selection(|_| false);
+        let mut commands: Vec<Command> = blocks.into_iter().map(Command::AddBlock).collect();
+        if !links.is_empty() { commands.push(Command::AddLinks(links)); }
+        self.history.push(Command::Batch(commands));
+        self.sync_recorded_positions();
+    }
+
+    fn toggle_size_mode(&mut self) {
+        let ids = self.graph.focused_blocks();
+        if ids.is_empty() { return; }
+        let mut before = vec![];
+        let mut after = vec![];
+        for id in ids {
+            let Some(block) = self.graph.block_mut(id) else { continue; };
+            before.push(block.clone());
+            let size_mode = if block.size_mode == SizeMode::Auto { SizeMode::Fixed } else { SizeMode::Auto };
+            block.set_size_mode(size_mode);
+            after.push(block.clone());
         }
-        block_remove_indices.dedup();
-        link_remove_indices.dedup();
-        for i in block_remove_indices.iter().rev() { self.blocks.remove(*i); }
-        for i in link_remove_indices.iter().rev() { self.links.remove(*i); }
+        self.history.push(Command::UpdateBlocks { before, after });
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.editor.is_some()
+    }
+
+    fn start_edition(&mut self, id: BlockId) {
+        let Some(block) = self.graph.block_mut(id) else { return; };
+        block.is_focused = true;
+        self.editor = Some(TextEditor::new(block));
+        self.drag = false;
+    }
+
+    // Resize the edited block to its new text
+    pub fn on_edition_changed(&mut self) {
+        let Some(editor) = &self.editor else { return; };
+        let EditTarget::Block(id) = editor.target else { return; };
+        let Some(block) = self.graph.block_mut(id) else { return; };
+        block.fit_text(&editor.text());
     }
 
-    fn get_block_at(&mut self, pos: Vector2<f32>) -> Option<Rc<RefCell<Block>>> {
-        let block = self.blocks
-            .iter()
-            .find(|block| {
-                let block = block.borrow();
-                block.pos.x < pos.x && block.pos.y < pos.y && block.pos.x + block.width > pos.x && block.pos.y + block.height > pos.y
-            });
-        match block {
-            Some(block) => Some(Rc::clone(block)),
-            None => None
+    pub fn commit_edition(&mut self) {
+        let Some(editor) = self.editor.take() else { return; };
+        let id = match editor.target {
+            EditTarget::Block(id) => id,
+            EditTarget::Port(id, port) => return self.update_port(id, port, &editor.text()),
+            EditTarget::Link(id) => {
+                let Some(link) = self.graph.link_mut(id) else { return; };
+                if link.label == editor.text() { return; }
+                let before = link.clone();
+                link.label = editor.text();
+                self.history.push(Command::UpdateLinks { before: vec![before], after: vec![link.clone()] });
+                return;
+            }
+        };
+        let Some(block) = self.graph.block_mut(id) else { return; };
+        let (title, body) = editor.label();
+        let mut before = block.clone();
+        before.update_text_layout(); // The block has been resized during the edition
+        if title == block.title && body == block.body {
+            *block = before;
+            return;
         }
+        block.set_label(title, body);
+        self.history.push(Command::UpdateBlocks { before: vec![before], after: vec![block.clone()] });
+    }
+
+    pub fn undo(&mut self) {
+        if self.drag || self.resize.is_some() { return; }
+        self.finish_moves();
+        self.commit_edition();
+        self.pending_links.clear();
+        self.history.undo(&mut self.graph);
+        self.sync_recorded_positions();
     }
 
-    fn get_focused_blocks(&mut self) -> Vec<Rc<RefCell<Block>>> {
-        self.blocks.iter_mut()
-            .filter(|block| block.borrow().is_focused)
-            .map(|block| Rc::clone(block))
-            .collect()
+    pub fn redo(&mut self) {
+        if self.drag || self.resize.is_some() { return; }
+        self.finish_moves();
+        self.commit_edition();
+        self.pending_links.clear();
+        self.history.redo(&mut self.graph);
+        self.sync_recorded_positions();
     }
 
-    pub fn update(&mut self, _dt: f32) {}
+    pub fn load_document(&mut self, document: &Document) {
+        self.editor = None;
+        self.connection = None;
+        self.resize = None;
+        self.rubber_band = None;
+        self.graph = Graph::from_document(document);
+        self.grid = document.grid;
+        self.link_layer = document.link_layer;
+        self.engine.reset();
+        self.highlighted_links.clear();
+        self.move_animation = None;
+        self.force_layout = None;
+        self.history.clear();
+        self.pending_links.clear();
+        self.drag = false;
+    }
+
+    pub fn open(&mut self, path: &Path) -> Result<(), DocumentError> {
+        let document = Document::load(path)?;
+        self.load_document(&document);
+        self.path = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    pub fn save(&mut self, path: &Path) -> Result<(), DocumentError> {
+        self.commit_edition();
+        self.finish_moves();
+        let mut document = self.graph.to_document();
+        document.grid = self.grid;
+        document.link_layer = self.link_layer;
+        document.save(path)?;
+        self.path = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if let Some(animation) = &mut self.move_animation {
+            if animation.update(dt, &mut self.graph) { self.move_animation = None; }
+        }
+        if let Some(force_layout) = &mut self.force_layout {
+            // The dragged blocks follow the mouse
+            let fixed = if self.drag { self.graph.focused_blocks().into_iter().collect() } else { HashSet::new() };
+            let has_moved = force_layout.update(&mut self.graph, &fixed);
+            if let (true, Some(event_sender)) = (has_moved, &self.event_sender) {
+                // The event loop is gone once the window is closed
+                if event_sender.send_event(AppEvent::Redraw).is_err() { self.event_sender = None; }
+            }
+        }
+        if self.show_results { self.engine.evaluate(&self.graph); }
+    }
+
+    // World area shown in the window, with a margin for what is drawn around the blocks like the ports
+    fn visible_area(&self) -> Rectangle {
+        let margin = Vector2::new(CULLING_MARGIN, CULLING_MARGIN);
+        Rectangle::new(self.camera.to_world(Vector2::ZERO - margin), self.camera.to_world(self.viewport_size + margin))
+    }
 
     pub fn render(&mut self, graphics: &mut Graphics2D) {
-        for block in &self.blocks {
-            block.borrow().render(graphics);
+        let camera = &self.camera.clone(); // Not borrowed while the links update their cached route
+        self.grid.render(camera, self.viewport_size, graphics);
+        let edited = self.editor.as_ref().map(|editor| editor.target);
+        let visible_area = self.visible_area();
+        let mut visible_blocks = self.graph.blocks_in(&visible_area);
+        self.graph.sort_by_z(&mut visible_blocks);
+        self.route_cache.retain(&self.graph);
+        let tolerance = flattening_tolerance(camera.zoom);
+
+        // The port under the cursor turns red when the dragged link can't be connected to it
+        let connection_target = self.connection_target();
+        let is_refused = matches!(connection_target, Some((_, Err(_))));
+        let highlight = match (self.connection, self.hovered_port) {
+            (Some(_), Some(hovered)) => Some((hovered, if is_refused { PORT_REFUSED_COLOR } else { PORT_HOVER_COLOR })),
+            (None, Some(hovered)) => Some((hovered, PORT_HOVER_COLOR)),
+            _ => None,
+        };
+
+        if self.link_layer == LinkLayer::Below { self.render_links(&visible_area, tolerance, graphics); }
+        // Each block covers the ports and the text of the blocks below it
+        for block in visible_blocks.iter().filter_map(|id| self.graph.block(*id)) {
+            if Some(EditTarget::Block(block.id)) == edited {
+                block.render_frame(camera, graphics);
+                if let Some(editor) = &self.editor { editor.render(block, camera, graphics); }
+            } else {
+                block.render(camera, graphics);
+            }
+            let highlight = highlight.filter(|((id, _), _)| *id == block.id).map(|((_, port), color)| (port, color));
+            block.render_ports(highlight, camera, graphics);
+        }
+        if self.link_layer == LinkLayer::Above { self.render_links(&visible_area, tolerance, graphics); }
+        if let (Some(editor), Some(EditTarget::Port(id, port))) = (&self.editor, edited) {
+            if let Some(block) = self.graph.block(id) {
+                let center = camera.to_screen(block.port_position(port)) - Vector2::new(0., PORT_FONT_SIZE * camera.zoom);
+                editor.render_line(center, PORT_FONT_SIZE * camera.zoom, graphics);
+            }
+        }
+        if self.show_results { self.engine.render(&self.graph, &visible_blocks, camera, graphics); }
+
+        if self.editor.is_none() {
+            if let Some(bounds) = self.focused_bounds() { render_handles(&camera.rect_to_screen(&bounds), self.cursor_handle(), graphics); }
         }
 
-        for link in &self.links {
-            link.render(self.mouse_position, graphics);
+        let mouse = self.mouse_world_position();
+        for from in &self.pending_links {
+            let Some(from) = self.graph.block(*from) else { continue; };
+            let start = from.port_position(PortRef::new(PortSide::Output, 0));
+            Route::bezier(start, mouse).draw(camera, 1., Color::BLACK, &[], graphics);
+        }
+        if let Some((source, port)) = self.connection {
+            if let Some(source) = self.graph.block(source) {
+                let position = source.port_position(port);
+                let route = if port.side == PortSide::Output { Route::bezier(position, mouse) } else { Route::bezier(mouse, position) };
+                let color = if is_refused { PORT_REFUSED_COLOR } else { Color::BLACK };
+                route.draw(camera, 1., color, &[], graphics);
+            }
+        }
+
+        render_guides(&self.guides, camera, graphics);
+        if let Some(rubber_band) = &self.rubber_band { rubber_band.render(camera, graphics); }
+        if let Some(handle) = self.cursor_handle() { render_resize_cursor(handle, self.mouse_position, graphics); }
+        if let Some(debug) = &mut self.debug {
+            debug.count_frame();
+            debug.render(&self.graph, &self.camera, graphics);
         }
     }
-}
\ No newline at end of file
+
+    fn render_links(&mut self, visible_area: &Rectangle, tolerance: f32, graphics: &mut Graphics2D) {
+        let camera = &self.camera;
+        let edited = self.editor.as_ref().map(|editor| editor.target);
+        for id in self.graph.links_in(visible_area) {
+            let Some(link) = self.graph.link(id) else { continue; };
+            let Some(cached) = self.route_cache.get(&self.graph, link, tolerance) else { continue; };
+            if self.highlighted_links.contains(&link.id) {
+                let points: Vec<Vector2<f32>> = cached.polyline.iter().map(|point| camera.to_screen(*point)).collect();
+                draw_polyline(&points, link.width + 4., HIGHLIGHT_COLOR, &[], graphics);
+            }
+            link.render(cached, Some(EditTarget::Link(link.id)) == edited, camera, graphics);
+        }
+        if let (Some(editor), Some(EditTarget::Link(id))) = (&self.editor, edited) {
+            let cached = self.graph.link(id).and_then(|link| self.route_cache.get(&self.graph, link, tolerance));
+            if let Some(cached) = cached {
+                editor.render_line(camera.to_screen(polyline_midpoint(&cached.polyline)), LABEL_FONT_SIZE * camera.zoom, graphics);
+            }
+        }
+    }
+}
@@ -0,0 +1,420 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+use speedy2d::color::Color;
+use speedy2d::dimen::Vector2;
+use speedy2d::font::FormattedTextBlock;
+use speedy2d::Graphics2D;
+
+use crate::block::Block;
+use crate::camera::Camera;
+use crate::graph::{BlockId, Graph};
+use crate::text::layout_text;
+
+const RESULT_FONT_SIZE: f32 = 12.;
+const RESULT_COLOR: Color = Color::from_rgb(0.1, 0.45, 0.2);
+const ERROR_COLOR: Color = Color::from_rgb(0.85, 0.15, 0.15);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Text(String),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Text(text) => write!(f, "\"{}\"", text),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EvalError {
+    Cycle, // The block is part of a cycle or depends on one
+    MissingInput,
+    TypeMismatch,
+    DivisionByZero,
+    Upstream(BlockId), // A block this one depends on has failed
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::Cycle => write!(f, "cycle"),
+            EvalError::MissingInput => write!(f, "missing input"),
+            EvalError::TypeMismatch => write!(f, "type mismatch"),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::Upstream(id) => write!(f, "block {} failed", id),
+        }
+    }
+}
+
+pub type NodeResult = Result<Vec<Value>, EvalError>;
+
+type ResultLayout = (String, Rc<FormattedTextBlock>);
+
+// Computes the values of the output ports from the values received on each input port
+pub trait Node {
+    fn evaluate(&self, inputs: &[Vec<Value>]) -> NodeResult;
+}
+
+// Builds the node of a block, returns None when the block is not of its kind
+pub type NodeFactory = Box<dyn Fn(&Block) -> Option<Box<dyn Node>>>;
+
+// A number or a "quoted text"
+pub struct Constant(pub Value);
+
+impl Node for Constant {
+    fn evaluate(&self, _inputs: &[Vec<Value>]) -> NodeResult {
+        Ok(vec![self.0.clone()])
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+// Folds all the numbers received, in the order of the input ports
+pub struct Arithmetic(pub Operator);
+
+impl Node for Arithmetic {
+    fn evaluate(&self, inputs: &[Vec<Value>]) -> NodeResult {
+        let mut numbers = inputs.iter().flatten().map(|value| match value {
+            Value::Number(number) => Ok(*number),
+            Value::Text(_) => Err(EvalError::TypeMismatch),
+        });
+        let mut result = numbers.next().ok_or(EvalError::MissingInput)??;
+        for number in numbers {
+            let number = number?;
+            result = match self.0 {
+                Operator::Add => result + number,
+                Operator::Subtract => result - number,
+                Operator::Multiply => result * number,
+                Operator::Divide if number == 0. => return Err(EvalError::DivisionByZero),
+                Operator::Divide => result / number,
+            };
+        }
+        Ok(vec![Value::Number(result)])
+    }
+}
+
+pub struct Concat;
+
+impl Node for Concat {
+    fn evaluate(&self, inputs: &[Vec<Value>]) -> NodeResult {
+        let text = inputs.iter().flatten().map(|value| match value {
+            Value::Number(number) => number.to_string(),
+            Value::Text(text) => text.clone(),
+        }).collect();
+        Ok(vec![Value::Text(text)])
+    }
+}
+
+// Blocks without a kind forward the first value received
+pub struct Passthrough;
+
+impl Node for Passthrough {
+    fn evaluate(&self, inputs: &[Vec<Value>]) -> NodeResult {
+        Ok(inputs.iter().flatten().next().cloned().into_iter().collect())
+    }
+}
+
+// The kind of a block is given by its title
+pub fn default_node(block: &Block) -> Box<dyn Node> {
+    let title = block.title.trim();
+    if let Ok(number) = title.parse::<f64>() { return Box::new(Constant(Value::Number(number))); }
+    if let Some(text) = title.strip_prefix('"').and_then(|text| text.strip_suffix('"')) {
+        return Box::new(Constant(Value::Text(text.to_string())));
+    }
+    match title.to_lowercase().as_str() {
+        "+" | "add" | "sum" => Box::new(Arithmetic(Operator::Add)),
+        "-" | "sub" => Box::new(Arithmetic(Operator::Subtract)),
+        "*" | "mul" | "product" => Box::new(Arithmetic(Operator::Multiply)),
+        "/" | "div" => Box::new(Arithmetic(Operator::Divide)),
+        "concat" => Box::new(Concat),
+        _ => Box::new(Passthrough),
+    }
+}
+
+// What a node result depends on, the node is evaluated again when it changes
+#[derive(Clone, PartialEq)]
+struct NodeState {
+    title: String,
+    nb_inputs: usize,
+    incoming: Vec<(BlockId, usize, usize)>, // (from, from_port, to_port) sorted by link id
+}
+
+impl NodeState {
+    fn new(block: &Block, incoming: Vec<(BlockId, usize, usize)>) -> Self {
+        Self {
+            title: block.title.clone(),
+            nb_inputs: block.inputs.len(),
+            incoming,
+        }
+    }
+}
+
+// Evaluates the graph in topological order, keeping the results until their inputs change
+#[derive(Default)]
+pub struct Engine {
+    factories: Vec<NodeFactory>,
+    states: HashMap<BlockId, NodeState>,
+    results: BTreeMap<BlockId, NodeResult>,
+    result_layouts: RefCell<(f32, HashMap<BlockId, ResultLayout>)>, // Displayed results at the current zoom level
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registered factories are tried from the last one, before the default kinds
+    pub fn register(&mut self, factory: NodeFactory) {
+        self.factories.push(factory);
+    }
+
+    pub fn result(&self, id: BlockId) -> Option<&NodeResult> {
+        self.results.get(&id)
+    }
+
+    // Value of an output port
+    pub fn output(&self, id: BlockId, port: usize) -> Option<&Value> {
+        self.results.get(&id)?.as_ref().ok()?.get(port)
+    }
+
+    // Forget every result, the next evaluation computes the whole graph
+    pub fn reset(&mut self) {
+        self.states.clear();
+        self.results.clear();
+    }
+
+    fn node(&self, block: &Block) -> Box<dyn Node> {
+        self.factories.iter().rev().find_map(|factory| factory(block)).unwrap_or_else(|| default_node(block))
+    }
+
+    // Evaluate the blocks whose state or inputs have changed, returns them in evaluation order
+    pub fn evaluate(&mut self, graph: &Graph) -> Vec<BlockId> {
+        self.results.retain(|id, _| graph.block(*id).is_some());
+        self.states.retain(|id, _| graph.block(*id).is_some());
+
+        let mut successors: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+        let mut incoming: HashMap<BlockId, Vec<(BlockId, usize, usize)>> = HashMap::new();
+        let mut in_degrees: HashMap<BlockId, usize> = graph.blocks().map(|block| (block.id, 0)).collect();
+        for link in graph.links() {
+            successors.entry(link.from).or_default().push(link.to);
+            incoming.entry(link.to).or_default().push((link.from, link.from_port, link.to_port));
+            *in_degrees.entry(link.to).or_default() += 1;
+        }
+
+        let mut dirty = HashSet::new();
+        for block in graph.blocks() {
+            let state = NodeState::new(block, incoming.remove(&block.id).unwrap_or_default());
+            if self.states.get(&block.id) != Some(&state) {
+                dirty.insert(block.id);
+                self.states.insert(block.id, state);
+            }
+        }
+
+        // Kahn's algorithm, the dirty flag flows from a node to its successors
+        let mut queue: VecDeque<BlockId> = in_degrees.iter().filter(|(_, degree)| **degree == 0).map(|(id, _)| *id).collect();
+        queue.make_contiguous().sort();
+        let mut evaluated = vec![];
+        while let Some(id) = queue.pop_front() {
+            let successors = successors.get(&id).map(Vec::as_slice).unwrap_or_default();
+            if dirty.contains(&id) {
+                let block = graph.block(id).unwrap();
+                let result = self.evaluate_block(block);
+                self.results.insert(id, result);
+                evaluated.push(id);
+                dirty.extend(successors);
+            }
+            for successor in successors {
+                let degree = in_degrees.get_mut(successor).unwrap();
+                *degree -= 1;
+                if *degree == 0 { queue.push_back(*successor); }
+            }
+        }
+
+        // The remaining blocks are in a cycle or downstream of one
+        for (id, degree) in in_degrees {
+            if degree == 0 { continue; }
+            self.results.insert(id, Err(EvalError::Cycle));
+            self.states.remove(&id); // Evaluate it again once the cycle is broken
+        }
+        evaluated
+    }
+
+    fn evaluate_block(&self, block: &Block) -> NodeResult {
+        let mut inputs = vec![vec![]; block.inputs.len()];
+        for (from, from_port, to_port) in &self.states[&block.id].incoming {
+            let Some(values) = inputs.get_mut(*to_port) else { continue; };
+            match self.results.get(from) {
+                Some(Ok(outputs)) => values.extend(outputs.get(*from_port).cloned()),
+                _ => return Err(EvalError::Upstream(*from)),
+            }
+        }
+        self.node(block).evaluate(&inputs)
+    }
+
+    // Text displayed under a block
+    pub fn display(&self, id: BlockId) -> Option<String> {
+        match self.results.get(&id)? {
+            Ok(values) if values.is_empty() => None,
+            Ok(values) => Some(values.iter().map(Value::to_string).collect::<Vec<_>>().join(", ")),
+            Err(error) => Some(format!("error: {}", error)),
+        }
+    }
+
+    // The results of the visible blocks are drawn under them, the layouts of the other ones are dropped
+    pub fn render(&self, graph: &Graph, visible_blocks: &[BlockId], camera: &Camera, graphics: &mut Graphics2D) {
+        let mut result_layouts = self.result_layouts.borrow_mut();
+        if result_layouts.0 != camera.zoom { *result_layouts = (camera.zoom, HashMap::new()); }
+        let mut previous_layouts = std::mem::take(&mut result_layouts.1);
+        for block in visible_blocks.iter().filter_map(|id| graph.block(*id)) {
+            let Some(text) = self.display(block.id) else { continue; };
+            let layout = match previous_layouts.remove(&block.id) {
+                Some((previous_text, layout)) if previous_text == text => layout,
+                _ => layout_text(&text, RESULT_FONT_SIZE * camera.zoom),
+            };
+            let color = if matches!(self.result(block.id), Some(Err(_))) { ERROR_COLOR } else { RESULT_COLOR };
+            graphics.draw_text(camera.to_screen(block.pos + Vector2::new(0., block.height + 4.)), color, &layout);
+            result_layouts.1.insert(block.id, (text, layout));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dataflow::{Engine, EvalError, Node, NodeResult, Value};
+    use crate::graph::{BlockId, Graph};
+    use crate::port::Port;
+    use crate::test_helpers::add_block;
+
+    fn add_node(graph: &mut Graph, title: &str, nb_inputs: usize) -> BlockId {
+        let id = add_block(graph, 0.);
+        let block = graph.block_mut(id).unwrap();
+        block.inputs = vec![Port::any(); nb_inputs];
+        block.set_label(title.to_string(), String::new());
+        id
+    }
+
+    fn set_title(graph: &mut Graph, id: BlockId, title: &str) {
+        graph.block_mut(id).unwrap().set_label(title.to_string(), String::new());
+    }
+
+    #[test]
+    fn evaluates_in_topological_order() {
+        let mut graph = Graph::new();
+        let sum = add_node(&mut graph, "+", 2);
+        let a = add_node(&mut graph, "2", 0);
+        let b = add_node(&mut graph, "3", 0);
+        graph.add_link(a, 0, sum, 0);
+        graph.add_link(b, 0, sum, 1);
+        let mut engine = Engine::new();
+        let evaluated = engine.evaluate(&graph);
+        assert_eq!(evaluated.last(), Some(&sum));
+        assert_eq!(engine.output(sum, 0), Some(&Value::Number(5.)));
+    }
+
+    #[test]
+    fn only_downstream_nodes_are_evaluated_again() {
+        let mut graph = Graph::new();
+        let a = add_node(&mut graph, "2", 0);
+        let b = add_node(&mut graph, "3", 0);
+        let product = add_node(&mut graph, "*", 1);
+        let display = add_node(&mut graph, "", 1);
+        graph.add_link(a, 0, product, 0);
+        graph.add_link(b, 0, product, 0);
+        graph.add_link(product, 0, display, 0);
+        let mut engine = Engine::new();
+        assert_eq!(engine.evaluate(&graph).len(), 4);
+        assert!(engine.evaluate(&graph).is_empty());
+
+        set_title(&mut graph, a, "4");
+        assert_eq!(engine.evaluate(&graph), vec![a, product, display]);
+        assert_eq!(engine.output(display, 0), Some(&Value::Number(12.)));
+    }
+
+    #[test]
+    fn new_links_invalidate_their_destination() {
+        let mut graph = Graph::new();
+        let a = add_node(&mut graph, "\"one\"", 0);
+        let b = add_node(&mut graph, "\"two\"", 0);
+        let concat = add_node(&mut graph, "concat", 1);
+        graph.add_link(a, 0, concat, 0);
+        let mut engine = Engine::new();
+        engine.evaluate(&graph);
+        graph.add_link(b, 0, concat, 0);
+        assert_eq!(engine.evaluate(&graph), vec![concat]);
+        assert_eq!(engine.output(concat, 0), Some(&Value::Text("onetwo".to_string())));
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let mut graph = Graph::new();
+        let source = add_node(&mut graph, "1", 0);
+        let a = add_node(&mut graph, "+", 2);
+        let b = add_node(&mut graph, "+", 1);
+        let after = add_node(&mut graph, "", 1);
+        graph.add_link(source, 0, a, 0);
+        graph.add_link(a, 0, b, 0);
+        let back = graph.add_link(b, 0, a, 1).unwrap();
+        graph.add_link(b, 0, after, 0);
+        let mut engine = Engine::new();
+        engine.evaluate(&graph);
+        assert_eq!(engine.output(source, 0), Some(&Value::Number(1.)));
+        for id in [a, b, after] {
+            assert_eq!(engine.result(id), Some(&Err(EvalError::Cycle)));
+        }
+
+        graph.remove_link(back);
+        engine.evaluate(&graph);
+        assert_eq!(engine.output(after, 0), Some(&Value::Number(1.)));
+    }
+
+    #[test]
+    fn errors_propagate_downstream() {
+        let mut graph = Graph::new();
+        let a = add_node(&mut graph, "1", 0);
+        let zero = add_node(&mut graph, "0", 0);
+        let divide = add_node(&mut graph, "/", 2);
+        let after = add_node(&mut graph, "", 1);
+        graph.add_link(a, 0, divide, 0);
+        graph.add_link(zero, 0, divide, 1);
+        graph.add_link(divide, 0, after, 0);
+        let mut engine = Engine::new();
+        engine.evaluate(&graph);
+        assert_eq!(engine.result(divide), Some(&Err(EvalError::DivisionByZero)));
+        assert_eq!(engine.result(after), Some(&Err(EvalError::Upstream(divide))));
+    }
+
+    #[test]
+    fn custom_nodes_can_be_registered() {
+        struct Negate;
+        impl Node for Negate {
+            fn evaluate(&self, inputs: &[Vec<Value>]) -> NodeResult {
+                match inputs.first().and_then(|values| values.first()) {
+                    Some(Value::Number(number)) => Ok(vec![Value::Number(-number)]),
+                    Some(_) => Err(EvalError::TypeMismatch),
+                    None => Err(EvalError::MissingInput),
+                }
+            }
+        }
+        let mut graph = Graph::new();
+        let a = add_node(&mut graph, "7", 0);
+        let negate = add_node(&mut graph, "neg", 1);
+        graph.add_link(a, 0, negate, 0);
+        let mut engine = Engine::new();
+        engine.register(Box::new(|block| (block.title == "neg").then(|| Box::new(Negate) as Box<dyn Node>)));
+        engine.evaluate(&graph);
+        assert_eq!(engine.output(negate, 0), Some(&Value::Number(-7.)));
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use speedy2d::color::Color;
+use speedy2d::dimen::Vector2;
+use speedy2d::Graphics2D;
+
+use crate::AppEvent;
+use crate::camera::Camera;
+use crate::graph::Graph;
+use crate::link::HIT_TOLERANCE;
+use crate::port::{PORT_HIT_RADIUS, PortRef, PortSide};
+use crate::render_helper::{draw_rect_border, draw_rectangle};
+use crate::routing::Route;
+use crate::text::layout_text;
+
+const BOUNDS_COLOR: Color = Color::from_rgb(1., 0., 1.);
+const HIT_REGION_COLOR: Color = Color::from_rgba(0., 0.8, 0.8, 0.2);
+const CONTROL_COLOR: Color = Color::RED;
+const CONTROL_POLYGON_COLOR: Color = Color::CYAN;
+const TEXT_COLOR: Color = Color::from_rgb(0.6, 0., 0.6);
+const PANEL_COLOR: Color = Color::from_rgba(1., 1., 1., 0.85);
+const FONT_SIZE: f32 = 12.;
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+
+// Geometry and counters drawn over the diagram to debug it
+pub struct DebugOverlay {
+    frames: VecDeque<Instant>, // Timestamps of the frames drawn during the last `FPS_WINDOW`
+    nb_updates: u64,
+    nb_redraws: u64,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugOverlay {
+    pub fn new() -> Self {
+        Self {
+            frames: VecDeque::new(),
+            nb_updates: 0,
+            nb_redraws: 0,
+        }
+    }
+
+    pub fn count_event(&mut self, event: &AppEvent) {
+        match event {
+            AppEvent::Update => self.nb_updates += 1,
+            AppEvent::Redraw => self.nb_redraws += 1,
+        }
+    }
+
+    pub fn count_frame(&mut self) {
+        let now = Instant::now();
+        self.frames.push_back(now);
+        while self.frames.front().is_some_and(|frame| now.duration_since(*frame) > FPS_WINDOW) { self.frames.pop_front(); }
+    }
+
+    pub fn fps(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn render(&self, graph: &Graph, camera: &Camera, graphics: &mut Graphics2D) {
+        for link in graph.links() {
+            let Some(route) = graph.link_route(link) else { continue; };
+            let points: Vec<Vector2<f32>> = route.points(32).into_iter().map(|point| camera.to_screen(point)).collect();
+            for segment in points.windows(2) { graphics.draw_line(segment[0], segment[1], 2. * HIT_TOLERANCE, HIT_REGION_COLOR); }
+            if let Route::Bezier(curve) = route {
+                let curve = curve.map(|point| camera.to_screen(point));
+                for segment in curve.windows(2) { graphics.draw_line(segment[0], segment[1], 0.5, CONTROL_POLYGON_COLOR); }
+                for point in curve { graphics.draw_circle(point, 2., CONTROL_COLOR); }
+            }
+            draw_label(&format!("#{}", link.id), camera.to_screen(route.midpoint()) + Vector2::new(4., 4.), graphics);
+        }
+        for block in graph.blocks() {
+            let bounds = camera.rect_to_screen(&block.bounds());
+            draw_rect_border(*bounds.top_left(), bounds.width(), bounds.height(), 1., BOUNDS_COLOR, graphics);
+            for (side, ports) in [(PortSide::Input, &block.inputs), (PortSide::Output, &block.outputs)] {
+                for index in 0..ports.len() {
+                    graphics.draw_circle(camera.to_screen(block.port_position(PortRef::new(side, index))), PORT_HIT_RADIUS, HIT_REGION_COLOR);
+                }
+            }
+            draw_label(&format!("#{}", block.id), *bounds.top_left() - Vector2::new(0., FONT_SIZE * 1.4), graphics);
+        }
+        let stats = format!("{} FPS  {} blocks  {} links  Update: {}  Redraw: {}", self.fps(), graph.blocks().count(), graph.links().count(), self.nb_updates, self.nb_redraws);
+        draw_label(&stats, Vector2::new(8., 8.), graphics);
+    }
+}
+
+fn draw_label(text: &str, pos: Vector2<f32>, graphics: &mut Graphics2D) {
+    let layout = layout_text(text, FONT_SIZE);
+    draw_rectangle(pos.x - 2., pos.y, layout.width() + 4., layout.height(), PANEL_COLOR, graphics);
+    graphics.draw_text(pos, TEXT_COLOR, &layout);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::AppEvent;
+    use crate::debug::DebugOverlay;
+
+    #[test]
+    fn events_and_frames_are_counted() {
+        let mut debug = DebugOverlay::new();
+        for event in [AppEvent::Update, AppEvent::Update, AppEvent::Redraw] { debug.count_event(&event); }
+        debug.count_frame();
+        debug.count_frame();
+        assert_eq!((debug.nb_updates, debug.nb_redraws, debug.fps()), (2, 1, 2));
+    }
+}
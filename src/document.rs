@@ -0,0 +1,312 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::block::SizeMode;
+use crate::grid::Grid;
+use crate::link::{ArrowShape, Dash};
+use crate::port::{default_ports, Port};
+use crate::z_order::LinkLayer;
+use crate::routing::LinkStyle;
+
+// Bump this whenever the on-disk layout changes in a non backward compatible way
+pub const DOCUMENT_VERSION: u32 = 2; // Version 1 had no link ids, it is upgraded when loaded
+pub const DOCUMENT_EXTENSION: &str = "json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockData {
+    pub id: u32,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    #[serde(default)]
+    pub size_mode: SizeMode,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    #[serde(default)]
+    pub z_index: u32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub body: String,
+    #[serde(default = "default_ports")]
+    pub inputs: Vec<Port>,
+    #[serde(default = "default_ports")]
+    pub outputs: Vec<Port>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LinkData {
+    pub id: u32,
+    pub from: u32,
+    #[serde(default)]
+    pub from_port: usize,
+    pub to: u32,
+    #[serde(default)]
+    pub to_port: usize,
+    #[serde(default)]
+    pub style: LinkStyle,
+    #[serde(default)]
+    pub start_arrow: ArrowShape,
+    #[serde(default = "default_end_arrow")]
+    pub end_arrow: ArrowShape,
+    #[serde(default = "default_link_color")]
+    pub color: String, // #rrggbb
+    #[serde(default = "default_link_width")]
+    pub width: f32,
+    #[serde(default)]
+    pub dash: Dash,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub label: String,
+}
+
+fn default_end_arrow() -> ArrowShape {
+    ArrowShape::Triangle
+}
+
+fn default_link_color() -> String {
+    String::from("#000000")
+}
+
+fn default_link_width() -> f32 {
+    1.
+}
+
+// RGB value of a "#rrggbb" color
+pub fn parse_color(color: &str) -> Option<u32> {
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    u32::from_str_radix(hex, 16).ok()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Document {
+    pub version: u32,
+    pub blocks: Vec<BlockData>,
+    pub links: Vec<LinkData>,
+    #[serde(default)]
+    pub grid: Grid,
+    #[serde(default)]
+    pub link_layer: LinkLayer,
+}
+
+// Only used to check the version before parsing the rest of the file
+#[derive(Deserialize)]
+struct DocumentHeader {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum DocumentError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    UnsupportedVersion(u32),
+    ReservedId(u32), // The ids of the next blocks and links are computed from the largest ones
+    ReservedZIndex { block: u32 }, // Same for the z-index of the next block
+    DuplicateBlockId(u32),
+    DuplicateLinkId(u32),
+    DanglingLink { from: u32, to: u32, missing: u32 },
+    UnknownPort { link: u32, block: u32, port: usize },
+    InvalidColor { link: u32, color: String },
+    InvalidGridSize(f32),
+}
+
+impl Display for DocumentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DocumentError::Io(e) => write!(f, "Unable to access the file: {}", e),
+            DocumentError::Parse(e) => write!(f, "Invalid document: {}", e),
+            DocumentError::UnsupportedVersion(version) => write!(f, "Unsupported document version {} (expected version {})", version, DOCUMENT_VERSION),
+            DocumentError::ReservedId(id) => write!(f, "Invalid document: the id {} is reserved", id),
+            DocumentError::ReservedZIndex { block } => write!(f, "Invalid document: the z-index of block {} is reserved", block),
+            DocumentError::DuplicateBlockId(id) => write!(f, "Invalid document: block id {} is used more than once", id),
+            DocumentError::DuplicateLinkId(id) => write!(f, "Invalid document: link id {} is used more than once", id),
+            DocumentError::DanglingLink { from, to, missing } => write!(f, "Invalid document: the link {} -> {} refers to the unknown block {}", from, to, missing),
+            DocumentError::UnknownPort { link, block, port } => write!(f, "Invalid document: the link {} refers to the unknown port {} of the block {}", link, port, block),
+            DocumentError::InvalidColor { link, color } => write!(f, "Invalid document: the color \"{}\" of the link {} isn't of the form #rrggbb", color, link),
+            DocumentError::InvalidGridSize(size) => write!(f, "Invalid document: the grid size {} isn't positive", size),
+        }
+    }
+}
+
+impl std::error::Error for DocumentError {}
+
+impl From<io::Error> for DocumentError {
+    fn from(e: io::Error) -> Self { DocumentError::Io(e) }
+}
+
+impl From<serde_json::Error> for DocumentError {
+    fn from(e: serde_json::Error) -> Self { DocumentError::Parse(e) }
+}
+
+// Give an id to the links saved without one, after the ids of the other links
+fn upgrade_from_v1(mut document: serde_json::Value) -> serde_json::Value {
+    document["version"] = DOCUMENT_VERSION.into();
+    let Some(links) = document["links"].as_array_mut() else { return document; };
+    let mut next_id = links.iter().filter_map(|link| link["id"].as_u64()).max().map_or(0, |id| id + 1);
+    for link in links.iter_mut().filter_map(|link| link.as_object_mut()) {
+        if link.contains_key("id") { continue; }
+        link.insert(String::from("id"), next_id.into());
+        next_id += 1;
+    }
+    document
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::new(vec![], vec![])
+    }
+}
+
+impl Document {
+    pub fn new(blocks: Vec<BlockData>, links: Vec<LinkData>) -> Self {
+        Self {
+            version: DOCUMENT_VERSION,
+            blocks,
+            links,
+            grid: Grid::default(),
+            link_layer: LinkLayer::default(),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, DocumentError> {
+        let header: DocumentHeader = serde_json::from_str(json)?;
+        let document: Document = match header.version {
+            1 => serde_json::from_value(upgrade_from_v1(serde_json::from_str(json)?))?,
+            DOCUMENT_VERSION => serde_json::from_str(json)?,
+            version => return Err(DocumentError::UnsupportedVersion(version)),
+        };
+        document.validate()?;
+        Ok(document)
+    }
+
+    pub fn to_json(&self) -> Result<String, DocumentError> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n'); // Keep the file friendly with line based tools (git, diff, ...)
+        Ok(json)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, DocumentError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DocumentError> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), DocumentError> {
+        if !Grid::is_valid_size(self.grid.size) { return Err(DocumentError::InvalidGridSize(self.grid.size)); }
+        let mut blocks = HashMap::new();
+        for block in &self.blocks {
+            if block.id == u32::MAX { return Err(DocumentError::ReservedId(block.id)); }
+            if block.z_index == u32::MAX { return Err(DocumentError::ReservedZIndex { block: block.id }); }
+            if blocks.insert(block.id, block).is_some() { return Err(DocumentError::DuplicateBlockId(block.id)); }
+        }
+        let mut link_ids = HashSet::new();
+        for link in &self.links {
+            if link.id == u32::MAX { return Err(DocumentError::ReservedId(link.id)); }
+            if !link_ids.insert(link.id) { return Err(DocumentError::DuplicateLinkId(link.id)); }
+            let Some(from) = blocks.get(&link.from) else {
+                return Err(DocumentError::DanglingLink { from: link.from, to: link.to, missing: link.from });
+            };
+            let Some(to) = blocks.get(&link.to) else {
+                return Err(DocumentError::DanglingLink { from: link.from, to: link.to, missing: link.to });
+            };
+            if link.from_port >= from.outputs.len() {
+                return Err(DocumentError::UnknownPort { link: link.id, block: link.from, port: link.from_port });
+            }
+            if link.to_port >= to.inputs.len() {
+                return Err(DocumentError::UnknownPort { link: link.id, block: link.to, port: link.to_port });
+            }
+            if parse_color(&link.color).is_none() {
+                return Err(DocumentError::InvalidColor { link: link.id, color: link.color.clone() });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::{BlockData, Document, DOCUMENT_VERSION, DocumentError, LinkData};
+    use crate::grid::{Grid, GridStyle};
+    use crate::port::default_ports;
+
+    fn block(id: u32) -> BlockData {
+        BlockData {
+            id,
+            x: 10. * id as f32,
+            y: -5.5,
+            width: 120.,
+            height: 60.,
+            size_mode: Default::default(),
+            pinned: id == 0,
+            z_index: id,
+            title: format!("Block {}", id),
+            body: String::from("Body\nwith two lines"),
+            inputs: default_ports(),
+            outputs: default_ports(),
+        }
+    }
+
+    fn link(id: u32, from: u32, to: u32) -> LinkData {
+        let json = format!(r#"{{ "id": {}, "from": {}, "to": {}, "label": "Label" }}"#, id, from, to);
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn documents_survive_a_round_trip() {
+        let document = Document::new(vec![block(0), block(1)], vec![link(0, 0, 1)]);
+        let json = document.to_json().unwrap();
+        let loaded = Document::from_json(&json).unwrap();
+        assert_eq!(loaded, document);
+        assert_eq!(loaded.to_json().unwrap(), json);
+    }
+
+    #[test]
+    fn unsupported_versions_are_refused() {
+        let json = r#"{ "version": 999, "blocks": [], "links": [] }"#;
+        assert!(matches!(Document::from_json(json), Err(DocumentError::UnsupportedVersion(999))));
+    }
+
+    #[test]
+    fn invalid_references_are_refused() {
+        let dangling = Document::new(vec![block(0)], vec![link(0, 0, 7)]).to_json().unwrap();
+        assert!(matches!(Document::from_json(&dangling), Err(DocumentError::DanglingLink { missing: 7, .. })));
+        let duplicate_blocks = Document::new(vec![block(0), block(0)], vec![]).to_json().unwrap();
+        assert!(matches!(Document::from_json(&duplicate_blocks), Err(DocumentError::DuplicateBlockId(0))));
+        let duplicate_links = Document::new(vec![block(0), block(1)], vec![link(3, 0, 1), link(3, 1, 0)]).to_json().unwrap();
+        assert!(matches!(Document::from_json(&duplicate_links), Err(DocumentError::DuplicateLinkId(3))));
+    }
+
+    #[test]
+    fn version_1_links_get_an_id() {
+        let json = r#"{ "version": 1, "blocks": [{ "id": 0, "x": 0, "y": 0, "width": 10, "height": 10 }], "links": [{ "id": 4, "from": 0, "to": 0 }, { "from": 0, "to": 0 }] }"#;
+        let document = Document::from_json(json).unwrap();
+        assert_eq!(document.version, DOCUMENT_VERSION);
+        assert_eq!(document.links.iter().map(|link| link.id).collect::<Vec<_>>(), vec![4, 5]);
+    }
+
+    #[test]
+    fn the_largest_id_and_z_index_are_refused() {
+        let json = Document::new(vec![block(u32::MAX)], vec![]).to_json().unwrap();
+        assert!(matches!(Document::from_json(&json), Err(DocumentError::ReservedId(u32::MAX))));
+        let mut document = Document::new(vec![block(2)], vec![]);
+        document.blocks[0].z_index = u32::MAX;
+        assert!(matches!(Document::from_json(&document.to_json().unwrap()), Err(DocumentError::ReservedZIndex { block: 2 })));
+    }
+
+    #[test]
+    fn the_grid_survives_a_round_trip() {
+        let mut document = Document { grid: Grid { size: 40., style: GridStyle::Dots, snap: true }, ..Document::default() };
+        let loaded = Document::from_json(&document.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.grid, document.grid);
+        document.grid.size = 0.;
+        assert!(matches!(Document::from_json(&document.to_json().unwrap()), Err(DocumentError::InvalidGridSize(_))));
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::{HashMap, HashSet};
+
+use speedy2d::dimen::Vector2;
+use speedy2d::shape::Rectangle;
+
+use crate::geometry::dot;
+use crate::graph::{BlockId, Graph};
+
+const LINK_LENGTH: f32 = 80.; // Rest length of the springs, between the block edges
+const SPRING_STRENGTH: f32 = 0.02;
+const REPULSION: f32 = 4000.;
+const REPULSION_RANGE: f32 = 600.; // Blocks further apart don't repel each other
+const OVERLAP_MARGIN: f32 = 20.; // Minimum space kept between two blocks
+const OVERLAP_STRENGTH: f32 = 0.5;
+const DAMPING: f32 = 0.8;
+const MAX_STEP: f32 = 30.; // Maximum distance covered by a block in a step
+const MAX_STEPS: usize = 500;
+const SETTLED_MOVEMENT: f32 = 0.05; // Average movement under which the layout is settled
+
+// What the forces depend on: the bounds and pins of the blocks, and the links
+type LayoutState = (Vec<(BlockId, Rectangle, bool)>, Vec<(BlockId, BlockId)>);
+
+fn layout_state(graph: &Graph) -> LayoutState {
+    let blocks = graph.blocks().map(|block| (block.id, block.bounds(), block.is_pinned)).collect();
+    let links = graph.links().map(|link| (link.from, link.to)).collect();
+    (blocks, links)
+}
+
+// Springs along the links, repulsion between the blocks and overlap avoidance
+#[derive(Default)]
+pub struct ForceLayout {
+    velocities: HashMap<BlockId, Vector2<f32>>,
+    settled_state: Option<LayoutState>, // Graph in which the blocks last settled
+}
+
+impl ForceLayout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Step until the blocks settle, then wait for the graph to change, returns whether the blocks have moved
+    pub fn update(&mut self, graph: &mut Graph, fixed: &HashSet<BlockId>) -> bool {
+        if self.settled_state.as_ref().is_some_and(|state| *state == layout_state(graph)) { return false; }
+        self.settled_state = None;
+        if self.step(graph, fixed) < SETTLED_MOVEMENT {
+            self.velocities.clear();
+            self.settled_state = Some(layout_state(graph));
+        }
+        true
+    }
+
+    // Move the blocks one step, the pinned and `fixed` blocks stay in place, returns the average movement
+    pub fn step(&mut self, graph: &mut Graph, fixed: &HashSet<BlockId>) -> f32 {
+        let blocks: Vec<(BlockId, Vector2<f32>, Vector2<f32>)> = graph.blocks()
+            .map(|block| (block.id, block.pos + Vector2::new(block.width, block.height) / 2., Vector2::new(block.width, block.height) / 2.))
+            .collect();
+        let index_of: HashMap<BlockId, usize> = blocks.iter().enumerate().map(|(index, (id, _, _))| (*id, index)).collect();
+        let mut forces = vec![Vector2::ZERO; blocks.len()];
+
+        for (i, (_, center, half_size)) in blocks.iter().enumerate() {
+            for (j, (_, other_center, other_half_size)) in blocks.iter().enumerate().skip(i + 1) {
+                let mut delta = *center - *other_center;
+                if delta.magnitude_squared() < 1e-4 { delta = Vector2::new((i as f32 - j as f32).signum(), 0.5); } // Separate stacked blocks
+                if delta.x.abs() > REPULSION_RANGE || delta.y.abs() > REPULSION_RANGE { continue; }
+                let distance = delta.magnitude();
+                let direction = delta / distance;
+                let mut force = direction * (REPULSION / distance.max(1.));
+                // Push overlapping blocks apart along the axis of least overlap
+                let overlap = *half_size + *other_half_size + Vector2::new(OVERLAP_MARGIN, OVERLAP_MARGIN) - Vector2::new(delta.x.abs(), delta.y.abs());
+                if overlap.x > 0. && overlap.y > 0. {
+                    force += if overlap.x < overlap.y {
+                        Vector2::new(overlap.x * delta.x.signum(), 0.)
+                    } else {
+                        Vector2::new(0., overlap.y * delta.y.signum())
+                    } * OVERLAP_STRENGTH;
+                }
+                forces[i] += force;
+                forces[j] -= force;
+            }
+        }
+
+        for link in graph.links() {
+            let (Some(&from), Some(&to)) = (index_of.get(&link.from), index_of.get(&link.to)) else { continue; };
+            if from == to { continue; }
+            let delta = blocks[to].1 - blocks[from].1;
+            let distance = delta.magnitude().max(1.);
+            let direction = delta / distance;
+            // The rest length is measured between the edges of the blocks facing each other
+            let extent = |half_size: Vector2<f32>| dot(half_size, Vector2::new(direction.x.abs(), direction.y.abs()));
+            let rest_length = extent(blocks[from].2) + extent(blocks[to].2) + LINK_LENGTH;
+            let force = direction * ((distance - rest_length) * SPRING_STRENGTH);
+            forces[from] += force;
+            forces[to] -= force;
+        }
+
+        let mut movement = 0.;
+        for ((id, _, _), force) in blocks.iter().zip(forces) {
+            let Some(block) = graph.block_mut(*id) else { continue; };
+            if block.is_pinned || fixed.contains(id) {
+                self.velocities.remove(id);
+                continue;
+            }
+            let velocity = self.velocities.entry(*id).or_insert(Vector2::ZERO);
+            *velocity = (*velocity + force) * DAMPING;
+            if velocity.magnitude() > MAX_STEP { *velocity = velocity.normalize().unwrap() * MAX_STEP; }
+            block.pos += *velocity;
+            movement += velocity.magnitude();
+        }
+        self.velocities.retain(|id, _| index_of.contains_key(id));
+        if blocks.is_empty() { 0. } else { movement / blocks.len() as f32 }
+    }
+
+    // Run the simulation until the blocks settle, returns the new position of the moved blocks
+    pub fn settle(graph: &Graph) -> Vec<(BlockId, Vector2<f32>)> {
+        let mut graph = graph.clone();
+        let mut layout = Self::new();
+        for _ in 0..MAX_STEPS {
+            if layout.step(&mut graph, &HashSet::new()) < SETTLED_MOVEMENT { break; }
+        }
+        graph.blocks().map(|block| (block.id, block.pos)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use speedy2d::dimen::Vector2;
+
+    use crate::block::Block;
+    use crate::force_layout::ForceLayout;
+    use crate::graph::Graph;
+
+    #[test]
+    fn overlapping_blocks_are_separated() {
+        let mut graph = Graph::new();
+        let a = graph.add_block(Block::new(Vector2::ZERO));
+        let b = graph.add_block(Block::new(Vector2::new(10., 5.)));
+        for (id, pos) in ForceLayout::settle(&graph) {
+            graph.block_mut(id).unwrap().pos = pos;
+        }
+        let (a, b) = (graph.block(a).unwrap(), graph.block(b).unwrap());
+        assert!(a.bounds().intersect(&b.bounds()).is_none());
+    }
+
+    #[test]
+    fn pinned_blocks_stay_fixed() {
+        let mut graph = Graph::new();
+        let a = graph.add_block(Block::new(Vector2::ZERO));
+        let b = graph.add_block(Block::new(Vector2::new(2000., 0.)));
+        graph.add_link(a, 0, b, 0);
+        graph.block_mut(a).unwrap().is_pinned = true;
+        let mut layout = ForceLayout::new();
+        for _ in 0..50 {
+            layout.step(&mut graph, &HashSet::new());
+        }
+        assert_eq!(graph.block(a).unwrap().pos, Vector2::ZERO);
+        assert!(graph.block(b).unwrap().pos.x < 2000.);
+    }
+
+    #[test]
+    fn the_layout_waits_once_settled() {
+        let mut graph = Graph::new();
+        let a = graph.add_block(Block::new(Vector2::ZERO));
+        graph.add_block(Block::new(Vector2::new(10., 5.)));
+        let mut layout = ForceLayout::new();
+        let nb_updates = (0..1000).take_while(|_| layout.update(&mut graph, &HashSet::new())).count();
+        assert!(nb_updates < 1000);
+        assert!(!layout.update(&mut graph, &HashSet::new()));
+        graph.block_mut(a).unwrap().pos += Vector2::new(5., 0.);
+        assert!(layout.update(&mut graph, &HashSet::new()));
+    }
+}
@@ -0,0 +1,50 @@
+use speedy2d::dimen::Vector2;
+use speedy2d::shape::Rectangle;
+
+type Point = Vector2<f32>;
+
+#[inline]
+pub fn dot(a: Point, b: Point) -> f32 {
+    a.x * b.x + a.y * b.y
+}
+
+pub fn distance_to_segment(point: Point, start: Point, end: Point) -> f32 {
+    let segment = end - start;
+    let length_squared = segment.magnitude_squared();
+    if length_squared == 0. { return (point - start).magnitude(); }
+    let t = (dot(point - start, segment) / length_squared).clamp(0., 1.);
+    (point - (start + segment * t)).magnitude()
+}
+
+pub fn distance_to_polyline(point: Point, polyline: &[Point]) -> f32 {
+    polyline.windows(2)
+        .map(|segment| distance_to_segment(point, segment[0], segment[1]))
+        .fold(f32::INFINITY, f32::min)
+}
+
+// Liang-Barsky clipping of the segment by the rectangle, touching counts as intersecting
+pub fn segment_intersects_rect(start: Point, end: Point, rect: &Rectangle) -> bool {
+    let delta = end - start;
+    let (mut t_min, mut t_max) = (0f32, 1f32);
+    let (min, max) = (rect.top_left(), rect.bottom_right());
+    for (d, from_min, to_max) in [(delta.x, start.x - min.x, max.x - start.x), (delta.y, start.y - min.y, max.y - start.y)] {
+        if d == 0. {
+            if from_min < 0. || to_max < 0. { return false; }
+            continue;
+        }
+        let (t0, t1) = (-from_min / d, to_max / d);
+        let (t0, t1) = (t0.min(t1), t0.max(t1));
+        t_min = t_min.max(t0);
+        t_max = t_max.min(t1);
+        if t_min > t_max { return false; }
+    }
+    true
+}
+
+pub fn bounding_box(points: &[Point]) -> Option<Rectangle> {
+    let first = *points.first()?;
+    let (min, max) = points.iter().fold((first, first), |(min, max), point| {
+        (Vector2::new(min.x.min(point.x), min.y.min(point.y)), Vector2::new(max.x.max(point.x), max.y.max(point.y)))
+    });
+    Some(Rectangle::new(min, max))
+}
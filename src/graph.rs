@@ -0,0 +1,376 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use speedy2d::color::Color;
+use speedy2d::dimen::Vector2;
+use speedy2d::shape::Rectangle;
+
+use crate::block::Block;
+use crate::document::{BlockData, Document, LinkData, parse_color};
+use crate::geometry::bounding_box;
+use crate::link::{color_to_hex, Link, LINK_COLORS};
+use crate::port::PortRef;
+use crate::routing::{LinkStyle, Route};
+use crate::spatial_index::SpatialIndex;
+
+pub type BlockId = u32;
+pub type LinkId = u32;
+
+const ROUTING_MARGIN: f32 = 50.; // Blocks this close to the endpoints of an orthogonal link can be in its way
+const ROUTE_MARGIN: f32 = 40.; // Max distance between a route and the blocks or control points it is built from
+
+// Owns every block and link of the diagram, links refer to their endpoints by id
+#[derive(Default, Clone)]
+pub struct Graph {
+    blocks: BTreeMap<BlockId, Block>,
+    links: BTreeMap<LinkId, Link>,
+    next_block_id: BlockId,
+    next_link_id: LinkId,
+    next_z_index: u32, // Above every block
+    index: RefCell<SpatialIndex>, // Bounds of the blocks, updated lazily before the queries
+    stale_blocks: RefCell<HashSet<BlockId>>, // Blocks borrowed mutably since the last update of the index
+    link_index: RefCell<SpatialIndex>, // Bounds of the links, updated after the blocks they depend on
+    stale_links: RefCell<HashSet<LinkId>>, // Links whose bounds may have changed, or without bounds
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_block(&mut self, mut block: Block) -> BlockId {
+        let id = self.next_block_id;
+        block.id = id;
+        block.z_index = self.next_z_index;
+        self.insert_block(block);
+        id
+    }
+
+    // Insert a block keeping its id (used when restoring blocks)
+    pub fn insert_block(&mut self, block: Block) {
+        self.next_block_id = self.next_block_id.max(block.id + 1);
+        self.next_z_index = self.next_z_index.max(block.z_index + 1);
+        self.stale_blocks.get_mut().insert(block.id);
+        self.blocks.insert(block.id, block);
+    }
+
+    // The caller is in charge of checking the `LinkRules`
+    pub fn add_link(&mut self, from: BlockId, from_port: usize, to: BlockId, to_port: usize) -> Option<LinkId> {
+        self.add_link_copy(Link::new(0, from, from_port, to, to_port))
+    }
+
+    // Insert the link with its style under a new id
+    pub fn add_link_copy(&mut self, mut link: Link) -> Option<LinkId> {
+        if !self.blocks.contains_key(&link.from) || !self.blocks.contains_key(&link.to) { return None; }
+        link.id = self.next_link_id;
+        let id = link.id;
+        self.insert_link(link);
+        Some(id)
+    }
+
+    pub fn insert_link(&mut self, link: Link) {
+        self.next_link_id = self.next_link_id.max(link.id + 1);
+        self.stale_links.get_mut().insert(link.id);
+        self.links.insert(link.id, link);
+    }
+
+    pub fn remove_link(&mut self, id: LinkId) -> Option<Link> {
+        self.link_index.get_mut().remove(id);
+        self.stale_links.get_mut().remove(&id);
+        self.links.remove(&id)
+    }
+
+    // Remove the blocks and all their incident links, returns what has been removed
+    pub fn remove_blocks(&mut self, ids: &[BlockId]) -> (Vec<Block>, Vec<Link>) {
+        let ids: HashSet<BlockId> = ids.iter().copied().collect();
+        self.update_index();
+        let blocks = ids.iter().filter_map(|id| self.blocks.remove(id)).collect();
+        for id in &ids {
+            // The orthogonal links around the block may get a shorter route
+            let Some(bounds) = self.index.get_mut().bounds(*id) else { continue; };
+            self.index.get_mut().remove(*id);
+            self.stale_links.get_mut().extend(self.link_index.get_mut().query(&bounds));
+        }
+        let mut links: Vec<Link> = vec![];
+        self.links.retain(|_, link| {
+            let is_incident = ids.contains(&link.from) || ids.contains(&link.to);
+            if is_incident { links.push(link.clone()); }
+            !is_incident
+        });
+        for link in &links {
+            self.link_index.get_mut().remove(link.id);
+            self.stale_links.get_mut().remove(&link.id);
+        }
+        (blocks, links)
+    }
+
+    pub fn block(&self, id: BlockId) -> Option<&Block> {
+        self.blocks.get(&id)
+    }
+
+    pub fn block_mut(&mut self, id: BlockId) -> Option<&mut Block> {
+        self.stale_blocks.get_mut().insert(id);
+        self.blocks.get_mut(&id)
+    }
+
+    pub fn link(&self, id: LinkId) -> Option<&Link> {
+        self.links.get(&id)
+    }
+
+    pub fn link_mut(&mut self, id: LinkId) -> Option<&mut Link> {
+        self.stale_links.get_mut().insert(id);
+        self.links.get_mut(&id)
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    // The focus doesn't change the bounds, the index stays valid
+    pub fn set_focus(&mut self, is_focused: impl Fn(&Block) -> bool) {
+        self.blocks.values_mut().for_each(|block| block.is_focused = is_focused(block));
+    }
+
+    pub fn links(&self) -> impl Iterator<Item = &Link> {
+        self.links.values()
+    }
+
+    // The selection doesn't change the bounds, the index stays valid
+    pub fn set_link_selection(&mut self, is_selected: impl Fn(&Link) -> bool) {
+        self.links.values_mut().for_each(|link| link.is_selected = is_selected(link));
+    }
+
+    // Re-index the blocks which may have moved or been resized, then the links which depend on them
+    fn update_index(&self) {
+        let mut index = self.index.borrow_mut();
+        let mut stale_blocks = self.stale_blocks.borrow_mut();
+        let mut link_index = self.link_index.borrow_mut();
+        let mut stale_links = self.stale_links.borrow_mut();
+        // The links near the old or the new bounds of a block are its incident links and the orthogonal links it may be in the way of
+        let mut changed_areas = vec![];
+        for id in stale_blocks.drain() {
+            let Some(block) = self.blocks.get(&id) else { continue; };
+            changed_areas.extend(index.bounds(id));
+            changed_areas.push(block.bounds());
+            index.update(id, block.bounds());
+        }
+        changed_areas.iter().for_each(|area| stale_links.extend(link_index.query(area)));
+        // The links to a missing block stay stale until it's inserted
+        stale_links.retain(|id| {
+            let Some(link) = self.links.get(id) else { return false; };
+            let Some(bounds) = self.compute_link_bounds(&index, link) else { return true; };
+            link_index.update(*id, bounds);
+            false
+        });
+    }
+
+    // From the bottom to the top, the blocks with the same index are ordered by id
+    pub fn sort_by_z(&self, ids: &mut [BlockId]) {
+        ids.sort_by_key(|id| (self.blocks.get(id).map_or(0, |block| block.z_index), *id));
+    }
+
+    // Above every other block, the raised blocks keep their relative order
+    // Returns false when they already are at the front
+    pub fn bring_to_front(&mut self, ids: &[BlockId]) -> bool {
+        let key = |block: &Block| (block.z_index, block.id);
+        let raised: HashSet<BlockId> = ids.iter().copied().collect();
+        let Some(lowest) = ids.iter().filter_map(|id| self.blocks.get(id)).map(key).min() else { return false; };
+        if self.blocks().all(|block| raised.contains(&block.id) || key(block) < lowest) { return false; }
+        let mut ids = ids.to_vec();
+        self.sort_by_z(&mut ids);
+        for id in ids {
+            let Some(block) = self.blocks.get_mut(&id) else { continue; };
+            block.z_index = self.next_z_index;
+            self.next_z_index += 1;
+        }
+        true
+    }
+
+    pub fn z_order(&self) -> Vec<BlockId> {
+        let mut ids: Vec<BlockId> = self.blocks.keys().copied().collect();
+        self.sort_by_z(&mut ids);
+        ids
+    }
+
+    // Topmost block containing the position
+    pub fn block_at(&self, pos: Vector2<f32>) -> Option<BlockId> {
+        self.update_index();
+        let mut ids = self.index.borrow().at(pos);
+        self.sort_by_z(&mut ids);
+        ids.into_iter().rev().find(|id| self.blocks[id].contains(pos))
+    }
+
+    // Blocks overlapping the rectangle, sorted by id
+    pub fn blocks_in(&self, rect: &Rectangle) -> Vec<BlockId> {
+        self.update_index();
+        self.index.borrow().query(rect)
+    }
+
+    // Port within `radius` of the position, on the topmost block, the ports covered by a block above are hidden
+    pub fn port_at(&self, pos: Vector2<f32>, radius: f32) -> Option<(BlockId, PortRef)> {
+        let radius_offset = Vector2::new(radius, radius);
+        let mut ids = self.blocks_in(&Rectangle::new(pos - radius_offset, pos + radius_offset));
+        self.sort_by_z(&mut ids);
+        for id in ids.into_iter().rev() {
+            let block = &self.blocks[&id];
+            if let Some(port) = block.port_at(pos, radius) { return Some((id, port)); }
+            if block.contains(pos) { return None; }
+        }
+        None
+    }
+
+    // Links which may go through the rectangle, sorted by id
+    pub fn links_in(&self, rect: &Rectangle) -> Vec<LinkId> {
+        self.update_index();
+        self.link_index.borrow().query(rect)
+    }
+
+    // Closest link within `tolerance` of the position
+    pub fn link_at(&self, pos: Vector2<f32>, tolerance: f32) -> Option<LinkId> {
+        let tolerance = Vector2::new(tolerance, tolerance);
+        let area = Rectangle::new(pos - tolerance, pos + tolerance);
+        self.links_in(&area).into_iter()
+            .filter_map(|id| {
+                let link = &self.links[&id];
+                let route = self.link_route(link)?;
+                Some((link.id, Link::distance_to(&route, pos)))
+            })
+            .filter(|(_, distance)| *distance <= tolerance.x)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(id, _)| id)
+    }
+
+    // The other blocks near an orthogonal link are the obstacles its route goes around
+    pub fn link_obstacles(&self, link: &Link) -> Vec<Rectangle> {
+        self.update_index();
+        self.find_obstacles(&self.index.borrow(), link)
+    }
+
+    // Any block overlapping this area can be in the way of an orthogonal link
+    fn routing_area(from: &Block, to: &Block) -> Rectangle {
+        let (from_bounds, to_bounds) = (from.bounds(), to.bounds());
+        let margin = Vector2::new(ROUTING_MARGIN, ROUTING_MARGIN);
+        Rectangle::new(
+            Vector2::new(from_bounds.top_left().x.min(to_bounds.top_left().x), from_bounds.top_left().y.min(to_bounds.top_left().y)) - margin,
+            Vector2::new(from_bounds.bottom_right().x.max(to_bounds.bottom_right().x), from_bounds.bottom_right().y.max(to_bounds.bottom_right().y)) + margin,
+        )
+    }
+
+    // Takes the index as it's called while the index is borrowed for its update
+    fn find_obstacles(&self, index: &SpatialIndex, link: &Link) -> Vec<Rectangle> {
+        let (Some(from), Some(to)) = (self.block(link.from), self.block(link.to)) else { return vec![]; };
+        if link.style != LinkStyle::Orthogonal { return vec![]; }
+        index.query(&Self::routing_area(from, to)).into_iter()
+            .filter(|id| *id != link.from && *id != link.to)
+            .map(|id| self.blocks[&id].bounds())
+            .collect()
+    }
+
+    // Area the route of the link can't leave, checked before computing the route itself
+    pub fn link_bounds(&self, link: &Link) -> Option<Rectangle> {
+        self.update_index();
+        self.link_index.borrow().bounds(link.id)
+    }
+
+    // The bounds of an orthogonal link cover its routing area, so that the blocks entering the area make it stale
+    fn compute_link_bounds(&self, index: &SpatialIndex, link: &Link) -> Option<Rectangle> {
+        let (from, to) = (self.block(link.from)?, self.block(link.to)?);
+        let points = match link.style {
+            LinkStyle::Bezier | LinkStyle::Straight => link.route(from, to, &[]).hull(),
+            LinkStyle::Orthogonal => self.find_obstacles(index, link).iter()
+                .chain([&Self::routing_area(from, to)])
+                .flat_map(|rect| [*rect.top_left(), *rect.bottom_right()])
+                .collect(),
+        };
+        let bounds = bounding_box(&points)?;
+        let margin = Vector2::new(ROUTE_MARGIN, ROUTE_MARGIN);
+        Some(Rectangle::new(*bounds.top_left() - margin, *bounds.bottom_right() + margin))
+    }
+
+    pub fn link_route(&self, link: &Link) -> Option<Route> {
+        let (from, to) = (self.block(link.from)?, self.block(link.to)?);
+        Some(link.route(from, to, &self.link_obstacles(link)))
+    }
+
+    pub fn focused_blocks(&self) -> Vec<BlockId> {
+        self.blocks().filter(|block| block.is_focused).map(|block| block.id).collect()
+    }
+
+    pub fn selected_links(&self) -> Vec<LinkId> {
+        self.links().filter(|link| link.is_selected).map(|link| link.id).collect()
+    }
+
+    pub fn to_document(&self) -> Document {
+        Document::new(self.blocks().map(block_data).collect(), self.links().map(link_data).collect())
+    }
+
+    // The blocks with the links between them, the links to the other blocks are dropped
+    pub fn to_document_of(&self, ids: &[BlockId]) -> Document {
+        let ids: BTreeSet<BlockId> = ids.iter().copied().collect();
+        let blocks = ids.iter().filter_map(|id| self.blocks.get(id)).map(block_data).collect();
+        let links = self.links().filter(|link| ids.contains(&link.from) && ids.contains(&link.to)).map(link_data).collect();
+        Document::new(blocks, links)
+    }
+
+    // The document is expected to be validated
+    pub fn from_document(document: &Document) -> Self {
+        let mut graph = Self::new();
+        for data in &document.blocks {
+            let mut block = Block::new_sized(Vector2::new(data.x, data.y), data.width, data.height);
+            block.id = data.id;
+            block.size_mode = data.size_mode;
+            block.is_pinned = data.pinned;
+            block.z_index = data.z_index;
+            block.inputs = data.inputs.clone();
+            block.outputs = data.outputs.clone();
+            block.set_label(data.title.clone(), data.body.clone());
+            graph.insert_block(block);
+        }
+        for data in &document.links {
+            let mut link = Link::new(data.id, data.from, data.from_port, data.to, data.to_port);
+            link.style = data.style;
+            link.start_arrow = data.start_arrow;
+            link.end_arrow = data.end_arrow;
+            link.color = Color::from_hex_rgb(parse_color(&data.color).unwrap_or(LINK_COLORS[0]));
+            link.width = data.width;
+            link.dash = data.dash;
+            link.label = data.label.clone();
+            graph.insert_link(link);
+        }
+        graph
+    }
+}
+
+fn block_data(block: &Block) -> BlockData {
+    BlockData {
+        id: block.id,
+        x: block.pos.x,
+        y: block.pos.y,
+        width: block.width,
+        height: block.height,
+        size_mode: block.size_mode,
+        pinned: block.is_pinned,
+        z_index: block.z_index,
+        title: block.title.clone(),
+        body: block.body.clone(),
+        inputs: block.inputs.clone(),
+        outputs: block.outputs.clone(),
+    }
+}
+
+fn link_data(link: &Link) -> LinkData {
+    LinkData {
+        id: link.id,
+        from: link.from,
+        from_port: link.from_port,
+        to: link.to,
+        to_port: link.to_port,
+        style: link.style,
+        start_arrow: link.start_arrow,
+        end_arrow: link.end_arrow,
+        color: format!("#{:06x}", color_to_hex(link.color)),
+        width: link.width,
+        dash: link.dash,
+        label: link.label.clone(),
+    }
+}
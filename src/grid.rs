@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use speedy2d::color::Color;
+use speedy2d::dimen::Vector2;
+use speedy2d::Graphics2D;
+
+use crate::camera::Camera;
+
+const LINE_COLOR: Color = Color::from_rgb(0.92, 0.92, 0.92);
+const MAJOR_LINE_COLOR: Color = Color::from_rgb(0.85, 0.85, 0.85);
+const DOT_COLOR: Color = Color::from_rgb(0.75, 0.75, 0.75);
+const MIN_LINE_SPACING: f32 = 8.; // px
+const MIN_DOT_SPACING: f32 = 16.; // px
+const SUBDIVISIONS: i64 = 5; // Cells of the grid in a major cell
+const SIZES: [f32; 5] = [5., 10., 20., 40., 80.]; // Chosen from the keyboard
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GridStyle {
+    Lines,
+    Dots,
+    Hidden,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Grid {
+    pub size: f32, // World size of a cell
+    pub style: GridStyle,
+    pub snap: bool,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            size: 20.,
+            style: GridStyle::Lines,
+            snap: false,
+        }
+    }
+}
+
+impl Grid {
+    pub fn toggle_snap(&mut self) {
+        self.snap = !self.snap;
+    }
+
+    pub fn next_style(&mut self) {
+        self.style = match self.style {
+            GridStyle::Lines => GridStyle::Dots,
+            GridStyle::Dots => GridStyle::Hidden,
+            GridStyle::Hidden => GridStyle::Lines,
+        };
+    }
+
+    pub fn is_valid_size(size: f32) -> bool {
+        size.is_finite() && size > 0.
+    }
+
+    pub fn next_size(&mut self) {
+        self.size = SIZES.into_iter().find(|size| *size > self.size).unwrap_or(SIZES[SIZES.len() - 1]);
+    }
+
+    pub fn previous_size(&mut self) {
+        self.size = SIZES.into_iter().rev().find(|size| *size < self.size).unwrap_or(SIZES[0]);
+    }
+
+    // Closest grid point, the point is returned as is when snapping is disabled
+    pub fn snap_point(&self, point: Vector2<f32>) -> Vector2<f32> {
+        if !self.snap || !Self::is_valid_size(self.size) { return point; }
+        Vector2::new((point.x / self.size).round() * self.size, (point.y / self.size).round() * self.size)
+    }
+
+    // The cells are merged when zooming out so they stay readable
+    fn step(&self, zoom: f32) -> f32 {
+        let min_spacing = if self.style == GridStyle::Dots { MIN_DOT_SPACING } else { MIN_LINE_SPACING };
+        let mut step = self.size;
+        while step * zoom < min_spacing {
+            step *= SUBDIVISIONS as f32;
+        }
+        step
+    }
+
+    pub fn render(&self, camera: &Camera, viewport_size: Vector2<f32>, graphics: &mut Graphics2D) {
+        if self.style == GridStyle::Hidden || !Self::is_valid_size(self.size) { return; }
+        let step = self.step(camera.zoom);
+        let top_left = camera.to_world(Vector2::ZERO);
+        let bottom_right = camera.to_world(viewport_size);
+        let columns = (top_left.x / step).floor() as i64..=(bottom_right.x / step).ceil() as i64;
+        let rows = (top_left.y / step).floor() as i64..=(bottom_right.y / step).ceil() as i64;
+        let is_major = |index: i64| index % SUBDIVISIONS == 0;
+        match self.style {
+            GridStyle::Lines => {
+                for column in columns {
+                    let x = camera.to_screen(Vector2::new(column as f32 * step, 0.)).x;
+                    let color = if is_major(column) { MAJOR_LINE_COLOR } else { LINE_COLOR };
+                    graphics.draw_line(Vector2::new(x, 0.), Vector2::new(x, viewport_size.y), 1., color);
+                }
+                for row in rows {
+                    let y = camera.to_screen(Vector2::new(0., row as f32 * step)).y;
+                    let color = if is_major(row) { MAJOR_LINE_COLOR } else { LINE_COLOR };
+                    graphics.draw_line(Vector2::new(0., y), Vector2::new(viewport_size.x, y), 1., color);
+                }
+            }
+            GridStyle::Dots => {
+                for column in columns {
+                    for row in rows.clone() {
+                        let pos = camera.to_screen(Vector2::new(column as f32 * step, row as f32 * step));
+                        graphics.draw_circle(pos, 1., DOT_COLOR);
+                    }
+                }
+            }
+            GridStyle::Hidden => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::dimen::Vector2;
+
+    use crate::grid::Grid;
+
+    #[test]
+    fn points_snap_to_the_closest_grid_point() {
+        let mut grid = Grid { snap: true, ..Grid::default() };
+        assert_eq!(grid.snap_point(Vector2::new(29., -31.)), Vector2::new(20., -40.));
+        grid.next_size();
+        assert_eq!(grid.snap_point(Vector2::new(29., -31.)), Vector2::new(40., -40.));
+        grid.size = 0.;
+        assert_eq!(grid.snap_point(Vector2::new(29., -31.)), Vector2::new(29., -31.));
+        grid.snap = false;
+        grid.size = 20.;
+        assert_eq!(grid.snap_point(Vector2::new(29., -31.)), Vector2::new(29., -31.));
+    }
+
+    #[test]
+    fn sizes_are_stepped_through() {
+        let mut grid = Grid { size: 15., ..Grid::default() };
+        grid.next_size();
+        assert_eq!(grid.size, 20.);
+        grid.previous_size();
+        grid.previous_size();
+        grid.previous_size();
+        assert_eq!(grid.size, 5.);
+    }
+}
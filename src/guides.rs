@@ -0,0 +1,223 @@
+use speedy2d::color::Color;
+use speedy2d::dimen::Vector2;
+use speedy2d::Graphics2D;
+use speedy2d::shape::Rectangle;
+
+use crate::camera::Camera;
+
+pub const SNAP_DISTANCE: f32 = 6.; // px
+const GUIDE_COLOR: Color = Color::from_rgb(1., 0.2, 0.6);
+const EPSILON: f32 = 0.01;
+
+// Extent of a rectangle along an axis (0 for x, 1 for y)
+fn span(rect: &Rectangle, axis: usize) -> (f32, f32) {
+    if axis == 0 { (rect.top_left().x, rect.bottom_right().x) } else { (rect.top_left().y, rect.bottom_right().y) }
+}
+
+fn point(axis: usize, along: f32, across: f32) -> Vector2<f32> {
+    if axis == 0 { Vector2::new(along, across) } else { Vector2::new(across, along) }
+}
+
+fn overlaps(a: (f32, f32), b: (f32, f32)) -> bool {
+    a.0 < b.1 && b.0 < a.1
+}
+
+fn translate(rect: &Rectangle, offset: Vector2<f32>) -> Rectangle {
+    Rectangle::new(*rect.top_left() + offset, *rect.bottom_right() + offset)
+}
+
+// What the moving rectangle has been snapped to along an axis
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Match {
+    Edge(f32), // Left, center or right of the moving rectangle aligned with the same coordinate of other rectangles
+    Between(usize, usize), // Same gap to the previous and the next rectangles
+    After(usize, usize), // Same gap to the previous rectangle as between it and the one before it
+    Before(usize, usize), // Same gap to the next rectangle as between it and the one after it
+}
+
+// A segment drawn while dragging, in world coordinates
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Guide {
+    pub from: Vector2<f32>,
+    pub to: Vector2<f32>,
+    pub is_gap: bool,
+}
+
+// The rectangles sorted along each axis, built once at the start of a drag so the queries are logarithmic
+// (but for the gap queries which stop at the first overlapping neighbour)
+pub struct GuideIndex {
+    rects: Vec<Rectangle>,
+    edges: [Vec<(f32, usize)>; 2], // Min, center and max of each rectangle along the axis
+    by_min: [Vec<usize>; 2],
+    by_max: [Vec<usize>; 2],
+}
+
+impl GuideIndex {
+    pub fn new(rects: Vec<Rectangle>) -> Self {
+        let build = |axis: usize| {
+            let mut edges: Vec<(f32, usize)> = rects.iter().enumerate().flat_map(|(index, rect)| {
+                let (min, max) = span(rect, axis);
+                [(min, index), ((min + max) / 2., index), (max, index)]
+            }).collect();
+            edges.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let mut by_min: Vec<usize> = (0..rects.len()).collect();
+            by_min.sort_by(|a, b| span(&rects[*a], axis).0.total_cmp(&span(&rects[*b], axis).0));
+            let mut by_max: Vec<usize> = (0..rects.len()).collect();
+            by_max.sort_by(|a, b| span(&rects[*a], axis).1.total_cmp(&span(&rects[*b], axis).1));
+            (edges, by_min, by_max)
+        };
+        let (x_edges, x_by_min, x_by_max) = build(0);
+        let (y_edges, y_by_min, y_by_max) = build(1);
+        Self {
+            rects,
+            edges: [x_edges, y_edges],
+            by_min: [x_by_min, y_by_min],
+            by_max: [x_by_max, y_by_max],
+        }
+    }
+
+    // Closest rectangle ending before `limit` along the axis and overlapping `across` on the other axis
+    fn previous(&self, axis: usize, limit: f32, across: (f32, f32)) -> Option<usize> {
+        let end = self.by_max[axis].partition_point(|index| span(&self.rects[*index], axis).1 <= limit);
+        self.by_max[axis][..end].iter().rev().copied().find(|index| overlaps(span(&self.rects[*index], 1 - axis), across))
+    }
+
+    // Closest rectangle starting after `limit` along the axis and overlapping `across` on the other axis
+    fn next(&self, axis: usize, limit: f32, across: (f32, f32)) -> Option<usize> {
+        let start = self.by_min[axis].partition_point(|index| span(&self.rects[*index], axis).0 < limit);
+        self.by_min[axis][start..].iter().copied().find(|index| overlaps(span(&self.rects[*index], 1 - axis), across))
+    }
+
+    // Best match along an axis as (offset to apply, match)
+    fn best_match(&self, moving: &Rectangle, axis: usize, threshold: f32) -> Option<(f32, Match)> {
+        let (min, max) = span(moving, axis);
+        let across = span(moving, 1 - axis);
+        let mut candidates: Vec<(f32, Match)> = vec![];
+
+        let edges = &self.edges[axis];
+        for feature in [min, (min + max) / 2., max] {
+            let index = edges.partition_point(|(value, _)| *value < feature);
+            for (value, _) in edges[index.saturating_sub(1)..(index + 1).min(edges.len())].iter() {
+                candidates.push((value - feature, Match::Edge(*value)));
+            }
+        }
+
+        let previous = self.previous(axis, min + threshold, across);
+        let next = self.next(axis, max - threshold, across);
+        if let (Some(previous), Some(next)) = (previous, next) {
+            let (previous_end, next_start) = (span(&self.rects[previous], axis).1, span(&self.rects[next], axis).0);
+            let target = (previous_end + next_start - (max - min)) / 2.;
+            candidates.push((target - min, Match::Between(previous, next)));
+        }
+        if let Some(previous) = previous {
+            let (previous_start, previous_end) = span(&self.rects[previous], axis);
+            if let Some(before) = self.previous(axis, previous_start, span(&self.rects[previous], 1 - axis)) {
+                let gap = previous_start - span(&self.rects[before], axis).1;
+                candidates.push((previous_end + gap - min, Match::After(before, previous)));
+            }
+        }
+        if let Some(next) = next {
+            let (next_start, next_end) = span(&self.rects[next], axis);
+            if let Some(after) = self.next(axis, next_end, span(&self.rects[next], 1 - axis)) {
+                let gap = span(&self.rects[after], axis).0 - next_end;
+                candidates.push((next_start - gap - max, Match::Before(next, after)));
+            }
+        }
+
+        candidates.into_iter()
+            .filter(|(offset, _)| offset.abs() <= threshold)
+            .min_by(|a, b| a.0.abs().total_cmp(&b.0.abs()))
+    }
+
+    // Offset moving the rectangle onto the closest guides (per axis, None when nothing is close enough)
+    // and the guides to display
+    pub fn snap(&self, moving: &Rectangle, threshold: f32) -> ([Option<f32>; 2], Vec<Guide>) {
+        let matches = [self.best_match(moving, 0, threshold), self.best_match(moving, 1, threshold)];
+        let offset = Vector2::new(matches[0].map_or(0., |m| m.0), matches[1].map_or(0., |m| m.0));
+        let snapped = translate(moving, offset);
+        let mut guides = vec![];
+        for (axis, found) in matches.iter().enumerate() {
+            let Some((_, found)) = found else { continue; };
+            self.add_guides(&snapped, axis, *found, &mut guides);
+        }
+        ([matches[0].map(|m| m.0), matches[1].map(|m| m.0)], guides)
+    }
+
+    fn add_guides(&self, moving: &Rectangle, axis: usize, found: Match, guides: &mut Vec<Guide>) {
+        let gap_guide = |from: &Rectangle, to: &Rectangle| {
+            let (a, b) = (span(from, 1 - axis), span(to, 1 - axis));
+            let across = (a.0.max(b.0) + a.1.min(b.1)) / 2.;
+            Guide { from: point(axis, span(from, axis).1, across), to: point(axis, span(to, axis).0, across), is_gap: true }
+        };
+        match found {
+            Match::Edge(value) => {
+                // A line across every rectangle aligned on this coordinate
+                let edges = &self.edges[axis];
+                let start = edges.partition_point(|(edge, _)| *edge < value - EPSILON);
+                let end = edges.partition_point(|(edge, _)| *edge <= value + EPSILON);
+                let (mut from, mut to) = span(moving, 1 - axis);
+                for (_, index) in &edges[start..end] {
+                    let (min, max) = span(&self.rects[*index], 1 - axis);
+                    from = from.min(min);
+                    to = to.max(max);
+                }
+                guides.push(Guide { from: point(axis, value, from), to: point(axis, value, to), is_gap: false });
+            }
+            Match::Between(previous, next) => {
+                guides.push(gap_guide(&self.rects[previous], moving));
+                guides.push(gap_guide(moving, &self.rects[next]));
+            }
+            Match::After(before, previous) => {
+                guides.push(gap_guide(&self.rects[before], &self.rects[previous]));
+                guides.push(gap_guide(&self.rects[previous], moving));
+            }
+            Match::Before(next, after) => {
+                guides.push(gap_guide(moving, &self.rects[next]));
+                guides.push(gap_guide(&self.rects[next], &self.rects[after]));
+            }
+        }
+    }
+}
+
+pub fn render_guides(guides: &[Guide], camera: &Camera, graphics: &mut Graphics2D) {
+    for guide in guides {
+        let (from, to) = (camera.to_screen(guide.from), camera.to_screen(guide.to));
+        graphics.draw_line(from, to, 1., GUIDE_COLOR);
+        if !guide.is_gap { continue; }
+        // Small ticks at both ends of the gaps
+        let normal = (to - from).normalize().map_or(Vector2::ZERO, |direction| Vector2::new(-direction.y, direction.x) * 4.);
+        graphics.draw_line(from - normal, from + normal, 1., GUIDE_COLOR);
+        graphics.draw_line(to - normal, to + normal, 1., GUIDE_COLOR);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::guides::GuideIndex;
+    use crate::test_helpers::rect;
+
+    #[test]
+    fn edges_and_centers_are_aligned() {
+        let index = GuideIndex::new(vec![rect(0., 0., 100., 40.)]);
+        let (offset, guides) = index.snap(&rect(3., 100., 100., 40.), 5.);
+        assert_eq!(offset, [Some(-3.), None]);
+        assert!(!guides.is_empty());
+        let (offset, _) = index.snap(&rect(28., 100., 40., 40.), 5.);
+        assert_eq!(offset, [Some(2.), None]); // Centers
+    }
+
+    #[test]
+    fn equal_gaps_are_matched() {
+        let index = GuideIndex::new(vec![rect(0., 0., 50., 50.), rect(200., 0., 50., 50.)]);
+        let (offset, _) = index.snap(&rect(98., 10., 50., 20.), 5.);
+        assert_eq!(offset[0], Some(2.)); // 50 on each side
+        let (offset, _) = index.snap(&rect(398., 10., 50., 20.), 5.);
+        assert_eq!(offset[0], Some(2.)); // Same gap as between the two others
+    }
+
+    #[test]
+    fn far_rectangles_are_ignored() {
+        let index = GuideIndex::new(vec![rect(0., 0., 100., 40.)]);
+        assert_eq!(index.snap(&rect(20., 100., 40., 40.), 5.).0, [None, None]);
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::VecDeque;
+
+use speedy2d::dimen::Vector2;
+
+use crate::block::Block;
+use crate::graph::{BlockId, Graph};
+use crate::layout::BlockMove;
+use crate::link::Link;
+
+pub const DEFAULT_HISTORY_DEPTH: usize = 100;
+
+// A reversible edit of the graph
+#[derive(Clone)]
+pub enum Command {
+    AddBlock(Block),
+    RemoveBlocks { blocks: Vec<Block>, links: Vec<Link> }, // Also used to remove links alone
+    AddLinks(Vec<Link>),
+    MoveBlocks { ids: Vec<BlockId>, delta: Vector2<f32> },
+    PlaceBlocks(Vec<BlockMove>), // Each block moves on its own
+    UpdateBlocks { before: Vec<Block>, after: Vec<Block> },
+    UpdateLinks { before: Vec<Link>, after: Vec<Link> },
+    Batch(Vec<Command>), // Applied in order, reverted in reverse order
+}
+
+impl Command {
+    pub fn apply(&self, graph: &mut Graph) {
+        match self {
+            Command::AddBlock(block) => graph.insert_block(block.clone()),
+            Command::RemoveBlocks { blocks, links } => {
+                let ids: Vec<BlockId> = blocks.iter().map(|block| block.id).collect();
+                graph.remove_blocks(&ids);
+                links.iter().for_each(|link| { graph.remove_link(link.id); });
+            }
+            Command::AddLinks(links) => links.iter().for_each(|link| graph.insert_link(link.clone())),
+            Command::MoveBlocks { ids, delta } => Self::move_blocks(graph, ids, *delta),
+            Command::PlaceBlocks(moves) => Self::place_blocks(graph, moves.iter().map(|(id, _, after)| (*id, *after))),
+            Command::UpdateBlocks { after, .. } => after.iter().for_each(|block| graph.insert_block(block.clone())),
+            Command::UpdateLinks { after, .. } => after.iter().for_each(|link| graph.insert_link(link.clone())),
+            Command::Batch(commands) => commands.iter().for_each(|command| command.apply(graph)),
+        }
+    }
+
+    pub fn revert(&self, graph: &mut Graph) {
+        match self {
+            Command::AddBlock(block) => { graph.remove_blocks(&[block.id]); }
+            Command::RemoveBlocks { blocks, links } => {
+                blocks.iter().for_each(|block| graph.insert_block(block.clone()));
+                links.iter().for_each(|link| graph.insert_link(link.clone()));
+            }
+            Command::AddLinks(links) => links.iter().for_each(|link| { graph.remove_link(link.id); }),
+            Command::MoveBlocks { ids, delta } => Self::move_blocks(graph, ids, Vector2::ZERO - *delta),
+            Command::PlaceBlocks(moves) => Self::place_blocks(graph, moves.iter().map(|(id, before, _)| (*id, *before))),
+            Command::UpdateBlocks { before, .. } => before.iter().for_each(|block| graph.insert_block(block.clone())),
+            Command::UpdateLinks { before, .. } => before.iter().for_each(|link| graph.insert_link(link.clone())),
+            Command::Batch(commands) => commands.iter().rev().for_each(|command| command.revert(graph)),
+        }
+    }
+
+    fn move_blocks(graph: &mut Graph, ids: &[BlockId], delta: Vector2<f32>) {
+        for id in ids {
+            if let Some(block) = graph.block_mut(*id) { block.pos += delta; }
+        }
+    }
+
+    fn place_blocks(graph: &mut Graph, positions: impl Iterator<Item = (BlockId, Vector2<f32>)>) {
+        for (id, pos) in positions {
+            if let Some(block) = graph.block_mut(id) { block.pos = pos; }
+        }
+    }
+}
+
+pub struct History {
+    undo_stack: VecDeque<Command>,
+    redo_stack: Vec<Command>,
+    max_depth: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_DEPTH)
+    }
+}
+
+impl History {
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            undo_stack: VecDeque::new(),
+            redo_stack: vec![],
+            max_depth,
+        }
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+        self.trim();
+    }
+
+    // Record a command which has already been applied to the graph
+    pub fn push(&mut self, command: Command) {
+        self.redo_stack.clear();
+        self.undo_stack.push_back(command);
+        self.trim();
+    }
+
+    pub fn undo(&mut self, graph: &mut Graph) -> bool {
+        let Some(command) = self.undo_stack.pop_back() else { return false; };
+        command.revert(graph);
+        self.redo_stack.push(command);
+        true
+    }
+
+    pub fn redo(&mut self, graph: &mut Graph) -> bool {
+        let Some(command) = self.redo_stack.pop() else { return false; };
+        command.apply(graph);
+        self.undo_stack.push_back(command);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    fn trim(&mut self) {
+        while self.undo_stack.len() > self.max_depth {
+            self.undo_stack.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::dimen::Vector2;
+    use speedy2d::window::MouseButton;
+
+    use crate::context::Context;
+    use crate::graph::Graph;
+    use crate::history::{Command, History};
+    use crate::test_helpers::add_block;
+
+    fn add_recorded_block(graph: &mut Graph, history: &mut History, x: f32) -> u32 {
+        let id = add_block(graph, x);
+        history.push(Command::AddBlock(graph.block(id).unwrap().clone()));
+        id
+    }
+
+    #[test]
+    fn undo_redo_add_block() {
+        let mut graph = Graph::new();
+        let mut history = History::default();
+        let id = add_recorded_block(&mut graph, &mut history, 0.);
+        assert!(history.undo(&mut graph));
+        assert!(graph.block(id).is_none());
+        assert!(history.redo(&mut graph));
+        assert!(graph.block(id).is_some());
+        assert!(!history.redo(&mut graph));
+    }
+
+    #[test]
+    fn undo_delete_restores_blocks_and_links() {
+        let mut graph = Graph::new();
+        let mut history = History::default();
+        let a = add_recorded_block(&mut graph, &mut history, 0.);
+        let b = add_recorded_block(&mut graph, &mut history, 200.);
+        let link = graph.add_link(a, 0, b, 0).unwrap();
+        let (blocks, links) = graph.remove_blocks(&[a]);
+        history.push(Command::RemoveBlocks { blocks, links });
+        assert!(graph.block(a).is_none());
+        assert_eq!(graph.links().count(), 0);
+        history.undo(&mut graph);
+        assert!(graph.block(a).is_some());
+        assert_eq!(graph.links().next().map(|link| link.id), Some(link));
+    }
+
+    #[test]
+    fn new_command_clears_redo() {
+        let mut graph = Graph::new();
+        let mut history = History::default();
+        add_recorded_block(&mut graph, &mut history, 0.);
+        history.undo(&mut graph);
+        add_recorded_block(&mut graph, &mut history, 100.);
+        assert!(!history.redo(&mut graph));
+    }
+
+    #[test]
+    fn history_depth_is_bounded() {
+        let mut graph = Graph::new();
+        let mut history = History::new(2);
+        for i in 0..5 { add_recorded_block(&mut graph, &mut history, i as f32 * 200.); }
+        assert!(history.undo(&mut graph));
+        assert!(history.undo(&mut graph));
+        assert!(!history.undo(&mut graph));
+        assert_eq!(graph.blocks().count(), 3);
+    }
+
+    #[test]
+    fn drag_is_a_single_move() {
+        let mut context = Context::new();
+        context.mouse_position = Vector2::new(10., 10.);
+        context.on_keydown("n".to_string());
+        context.mouse_position = Vector2::new(20., 20.);
+        context.on_mouse_clicked(MouseButton::Left);
+        context.drag = true;
+        for i in 1..=10 {
+            let position = Vector2::new(20. + i as f32 * 5., 20.);
+            context.move_block(position);
+            context.mouse_position = position;
+        }
+        context.end_drag();
+        assert_eq!(context.graph().blocks().next().unwrap().pos, Vector2::new(60., 10.));
+        context.undo();
+        assert_eq!(context.graph().blocks().next().unwrap().pos, Vector2::new(10., 10.));
+        context.undo();
+        assert_eq!(context.graph().blocks().count(), 0);
+    }
+}
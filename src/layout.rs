@@ -0,0 +1,288 @@
+use std::collections::{HashMap, HashSet};
+
+use speedy2d::dimen::Vector2;
+use speedy2d::window::UserEventSender;
+
+use crate::animation::{Animation, EasingFunction};
+use crate::graph::{BlockId, Graph};
+use crate::resize::blocks_bounds;
+use crate::AppEvent;
+
+const LAYER_GAP: f32 = 80.; // Horizontal space between two layers
+const NODE_GAP: f32 = 30.; // Vertical space between two blocks of a layer
+const DUMMY_HEIGHT: f32 = 10.; // Space kept for the links crossing a layer
+const ORDERING_SWEEPS: usize = 12;
+const PLACEMENT_SWEEPS: usize = 4;
+pub const LAYOUT_DURATION: f32 = 400.; // ms
+
+// (block, start position, end position)
+pub type BlockMove = (BlockId, Vector2<f32>, Vector2<f32>);
+
+// Moves blocks smoothly to their new positions
+pub struct MoveAnimation {
+    animation: Animation,
+    moves: Vec<BlockMove>,
+}
+
+impl MoveAnimation {
+    pub fn new(moves: Vec<BlockMove>, event_sender: UserEventSender<AppEvent>) -> Self {
+        let mut animation = Animation::new(0., 1., LAYOUT_DURATION, EasingFunction::SmootherStep, event_sender);
+        animation.start();
+        Self { animation, moves }
+    }
+
+    // Returns true once the blocks have reached their end position
+    pub fn update(&mut self, dt: f32, graph: &mut Graph) -> bool {
+        self.animation.update(dt);
+        if self.animation.is_ended {
+            self.finish(graph);
+            return true;
+        }
+        for (id, from, to) in &self.moves {
+            if let Some(block) = graph.block_mut(*id) { block.pos = *from + (*to - *from) * self.animation.value; }
+        }
+        false
+    }
+
+    pub fn finish(&self, graph: &mut Graph) {
+        for (id, _, to) in &self.moves {
+            if let Some(block) = graph.block_mut(*id) { block.pos = *to; }
+        }
+    }
+}
+
+// Sugiyama style layout of the `ids` blocks, only the links between them are considered:
+// the cycles are broken, the blocks are assigned to layers following the links from left to right,
+// ordered in their layer to reduce the crossings, then placed next to their neighbours.
+// Returns the new position of each block, the layout starts at the top left corner of the blocks.
+pub fn layered_layout(graph: &Graph, ids: &[BlockId]) -> Vec<(BlockId, Vector2<f32>)> {
+    let blocks: Vec<BlockId> = ids.iter().copied().filter(|id| graph.block(*id).is_some()).collect();
+    let Some(bounds) = blocks_bounds(blocks.iter().filter_map(|id| graph.block(*id))) else { return vec![]; };
+    let index_of: HashMap<BlockId, usize> = blocks.iter().enumerate().map(|(index, id)| (*id, index)).collect();
+    let edges: Vec<(usize, usize)> = graph.links()
+        .filter(|link| link.from != link.to)
+        .filter_map(|link| Some((*index_of.get(&link.from)?, *index_of.get(&link.to)?)))
+        .collect();
+    let edges = remove_cycles(blocks.len(), edges);
+    let mut layer_of = assign_layers(blocks.len(), &edges);
+
+    // Links spanning several layers go through dummy nodes, one per crossed layer
+    let mut nb_nodes = blocks.len();
+    let mut short_edges = vec![];
+    for (from, to) in edges {
+        let mut previous = from;
+        for layer in layer_of[from] + 1..layer_of[to] {
+            layer_of.push(layer);
+            short_edges.push((previous, nb_nodes));
+            previous = nb_nodes;
+            nb_nodes += 1;
+        }
+        short_edges.push((previous, to));
+    }
+    let nb_layers = layer_of.iter().max().map_or(0, |max| max + 1);
+    let mut layers: Vec<Vec<usize>> = vec![vec![]; nb_layers];
+    for (node, layer) in layer_of.iter().enumerate() {
+        layers[*layer].push(node);
+    }
+    // Start from the current vertical order to keep the diagram familiar
+    let y_of = |node: usize| blocks.get(node).and_then(|id| graph.block(*id)).map_or(f32::MAX, |block| block.pos.y);
+    for layer in &mut layers {
+        layer.sort_by(|a, b| y_of(*a).total_cmp(&y_of(*b)));
+    }
+    let predecessors = adjacency(nb_nodes, short_edges.iter().map(|(from, to)| (*to, *from)));
+    let successors = adjacency(nb_nodes, short_edges.iter().copied());
+    order_layers(&mut layers, &predecessors, &successors);
+
+    let heights: Vec<f32> = (0..nb_nodes).map(|node| blocks.get(node).and_then(|id| graph.block(*id)).map_or(DUMMY_HEIGHT, |block| block.height)).collect();
+    let centers = place_in_layers(&layers, &heights, &predecessors, &successors);
+    let min_top = (0..nb_nodes).map(|node| centers[node] - heights[node] / 2.).fold(f32::MAX, f32::min);
+
+    let mut x = bounds.top_left().x;
+    let mut positions = vec![];
+    for layer in &layers {
+        let mut layer_width: f32 = 0.;
+        for node in layer {
+            let Some(block) = blocks.get(*node).and_then(|id| graph.block(*id)) else { continue; };
+            let y = bounds.top_left().y + centers[*node] - heights[*node] / 2. - min_top;
+            positions.push((block.id, Vector2::new(x, y)));
+            layer_width = layer_width.max(block.width);
+        }
+        x += layer_width + LAYER_GAP;
+    }
+    positions
+}
+
+fn adjacency(nb_nodes: usize, edges: impl Iterator<Item = (usize, usize)>) -> Vec<Vec<usize>> {
+    let mut adjacency = vec![vec![]; nb_nodes];
+    for (from, to) in edges {
+        adjacency[from].push(to);
+    }
+    adjacency
+}
+
+// Reverse the links going back to a block being visited by a depth first search
+fn remove_cycles(nb_nodes: usize, edges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    let successors = adjacency(nb_nodes, edges.iter().copied());
+    let mut visiting = vec![false; nb_nodes];
+    let mut visited = vec![false; nb_nodes];
+    let mut back_edges = HashSet::new();
+    for root in 0..nb_nodes {
+        if visited[root] { continue; }
+        let mut stack = vec![(root, 0)];
+        visiting[root] = true;
+        while let Some(&(node, next)) = stack.last() {
+            let Some(&to) = successors[node].get(next) else {
+                visiting[node] = false;
+                visited[node] = true;
+                stack.pop();
+                continue;
+            };
+            stack.last_mut().unwrap().1 += 1;
+            if visiting[to] {
+                back_edges.insert((node, to));
+            } else if !visited[to] {
+                visiting[to] = true;
+                stack.push((to, 0));
+            }
+        }
+    }
+    edges.into_iter().map(|(from, to)| if back_edges.contains(&(from, to)) { (to, from) } else { (from, to) }).collect()
+}
+
+// Longest path layering: each block is one layer after its furthest predecessor
+fn assign_layers(nb_nodes: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let successors = adjacency(nb_nodes, edges.iter().copied());
+    let mut in_degrees = vec![0; nb_nodes];
+    edges.iter().for_each(|(_, to)| in_degrees[*to] += 1);
+    let mut queue: Vec<usize> = (0..nb_nodes).filter(|node| in_degrees[*node] == 0).collect();
+    let mut layers = vec![0; nb_nodes];
+    while let Some(node) = queue.pop() {
+        for &to in &successors[node] {
+            layers[to] = layers[to].max(layers[node] + 1);
+            in_degrees[to] -= 1;
+            if in_degrees[to] == 0 { queue.push(to); }
+        }
+    }
+    layers
+}
+
+// Barycenter heuristic, sweeping down then up and keeping the ordering with the fewest crossings
+fn order_layers(layers: &mut [Vec<usize>], predecessors: &[Vec<usize>], successors: &[Vec<usize>]) {
+    let mut best = layers.to_vec();
+    let mut best_crossings = count_crossings(layers, successors);
+    for sweep in 0..ORDERING_SWEEPS {
+        let downward = sweep % 2 == 0;
+        let range: Vec<usize> = if downward { (1..layers.len()).collect() } else { (0..layers.len().saturating_sub(1)).rev().collect() };
+        for layer in range {
+            let (fixed, neighbours) = if downward { (layer - 1, predecessors) } else { (layer + 1, successors) };
+            let rank: HashMap<usize, usize> = layers[fixed].iter().enumerate().map(|(rank, node)| (*node, rank)).collect();
+            let barycenters: HashMap<usize, f32> = layers[layer].iter().enumerate().map(|(position, node)| {
+                let ranks: Vec<f32> = neighbours[*node].iter().filter_map(|neighbour| rank.get(neighbour)).map(|rank| *rank as f32).collect();
+                // A node without neighbours keeps its place
+                let barycenter = if ranks.is_empty() { position as f32 } else { ranks.iter().sum::<f32>() / ranks.len() as f32 };
+                (*node, barycenter)
+            }).collect();
+            layers[layer].sort_by(|a, b| barycenters[a].total_cmp(&barycenters[b]));
+        }
+        let crossings = count_crossings(layers, successors);
+        if crossings < best_crossings {
+            best_crossings = crossings;
+            best = layers.to_vec();
+        }
+    }
+    layers.clone_from_slice(&best);
+}
+
+fn count_crossings(layers: &[Vec<usize>], successors: &[Vec<usize>]) -> usize {
+    let mut crossings = 0;
+    for pair in layers.windows(2) {
+        let rank: HashMap<usize, usize> = pair[1].iter().enumerate().map(|(rank, node)| (*node, rank)).collect();
+        let edges: Vec<(usize, usize)> = pair[0].iter().enumerate()
+            .flat_map(|(from, node)| successors[*node].iter().filter_map(|to| rank.get(to)).map(move |to| (from, *to)))
+            .collect();
+        for (i, a) in edges.iter().enumerate() {
+            crossings += edges[i + 1..].iter().filter(|b| (a.0 < b.0 && a.1 > b.1) || (a.0 > b.0 && a.1 < b.1)).count();
+        }
+    }
+    crossings
+}
+
+// Vertical center of each node, pulled towards its neighbours while keeping the order of the layer
+fn place_in_layers(layers: &[Vec<usize>], heights: &[f32], predecessors: &[Vec<usize>], successors: &[Vec<usize>]) -> Vec<f32> {
+    let mut centers = vec![0.; heights.len()];
+    for layer in layers {
+        stack(layer, heights, &mut centers, |_| 0.);
+    }
+    for sweep in 0..PLACEMENT_SWEEPS {
+        let downward = sweep % 2 == 0;
+        let neighbours = if downward { predecessors } else { successors };
+        let order: Vec<&Vec<usize>> = if downward { layers.iter().collect() } else { layers.iter().rev().collect() };
+        for layer in order {
+            let desired: HashMap<usize, f32> = layer.iter().map(|node| {
+                let linked = &neighbours[*node];
+                let center = if linked.is_empty() { centers[*node] } else { linked.iter().map(|other| centers[*other]).sum::<f32>() / linked.len() as f32 };
+                (*node, center)
+            }).collect();
+            stack(layer, heights, &mut centers, |node| desired[&node]);
+        }
+    }
+    centers
+}
+
+// Place the nodes of a layer from top to bottom as close as possible to their desired center
+fn stack(layer: &[usize], heights: &[f32], centers: &mut [f32], desired: impl Fn(usize) -> f32) {
+    let mut bottom: Option<f32> = None;
+    for node in layer {
+        let mut top = desired(*node) - heights[*node] / 2.;
+        if let Some(bottom) = bottom { top = top.max(bottom + NODE_GAP); }
+        centers[*node] = top + heights[*node] / 2.;
+        bottom = Some(top + heights[*node]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::dimen::Vector2;
+
+    use crate::block::Block;
+    use crate::graph::Graph;
+    use crate::layout::layered_layout;
+
+    #[test]
+    fn links_go_from_left_to_right() {
+        let mut graph = Graph::new();
+        let ids: Vec<u32> = (0..4).map(|i| graph.add_block(Block::new(Vector2::new(0., i as f32 * 10.)))).collect();
+        graph.add_link(ids[3], 0, ids[2], 0);
+        graph.add_link(ids[2], 0, ids[1], 0);
+        graph.add_link(ids[1], 0, ids[0], 0);
+        let positions = layered_layout(&graph, &ids);
+        let x = |id| positions.iter().find(|(other, _)| *other == id).unwrap().1.x;
+        assert!(x(ids[3]) < x(ids[2]) && x(ids[2]) < x(ids[1]) && x(ids[1]) < x(ids[0]));
+    }
+
+    #[test]
+    fn cycles_are_laid_out_in_distinct_layers() {
+        let mut graph = Graph::new();
+        let ids: Vec<u32> = (0..3).map(|_| graph.add_block(Block::new(Vector2::ZERO))).collect();
+        graph.add_link(ids[0], 0, ids[1], 0);
+        graph.add_link(ids[1], 0, ids[2], 0);
+        graph.add_link(ids[2], 0, ids[0], 0);
+        let mut x: Vec<f32> = layered_layout(&graph, &ids).iter().map(|(_, pos)| pos.x).collect();
+        x.sort_by(f32::total_cmp);
+        assert!(x[0] < x[1] && x[1] < x[2]);
+    }
+
+    #[test]
+    fn blocks_of_a_layer_do_not_overlap() {
+        let mut graph = Graph::new();
+        let ids: Vec<u32> = (0..5).map(|_| graph.add_block(Block::new(Vector2::ZERO))).collect();
+        for id in &ids[1..] {
+            graph.add_link(ids[0], 0, *id, 0);
+        }
+        let positions = layered_layout(&graph, &ids);
+        let mut layer: Vec<f32> = positions.iter().filter(|(id, _)| *id != ids[0]).map(|(_, pos)| pos.y).collect();
+        layer.sort_by(f32::total_cmp);
+        let height = graph.block(ids[1]).unwrap().height;
+        assert!(layer.windows(2).all(|pair| pair[1] - pair[0] >= height));
+    }
+}
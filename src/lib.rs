@@ -0,0 +1,39 @@
+pub mod context;
+pub mod camera;
+pub mod align;
+pub mod analysis;
+pub mod animation;
+pub mod clipboard;
+pub mod dataflow;
+pub mod debug;
+pub mod document;
+pub mod force_layout;
+pub mod geometry;
+pub mod graph;
+pub mod grid;
+pub mod guides;
+pub mod history;
+pub mod layout;
+pub mod text;
+pub mod text_editor;
+pub mod block;
+pub mod link;
+pub mod port;
+pub mod render_helper;
+pub mod resize;
+pub mod routing;
+pub mod selection;
+pub mod spatial_index;
+pub mod z_order;
+
+#[cfg(test)]
+mod test_helpers;
+
+#[macro_use]
+extern crate derivative;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum AppEvent {
+    Update,
+    Redraw,
+}
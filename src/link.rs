@@ -1,51 +1,271 @@
-use std::cell::{Ref, RefCell};
-use std::ops::Mul;
-use std::ptr;
+use std::cell::RefCell;
 use std::rc::Rc;
 
-use speedy2d::color::Color;
-use speedy2d::dimen::{Vec2, Vector2};
-use speedy2d::font::{Font, FormattedTextBlock};
+use serde::{Deserialize, Serialize};
+use speedy2d::dimen::Vector2;
+use speedy2d::font::FormattedTextBlock;
 use speedy2d::Graphics2D;
+use speedy2d::color::Color;
+use speedy2d::shape::Rectangle;
 
 use crate::block::Block;
-use crate::render_helper::draw_bezier_curve;
+use crate::camera::Camera;
+use crate::graph::{BlockId, Graph, LinkId};
+use crate::port::{PortRef, PortSide};
+use crate::render_helper::{draw_polyline, draw_rectangle};
+use crate::routing::{CachedRoute, LinkStyle, orthogonal_route, polyline_midpoint, Route};
+use crate::text::layout_text;
+
+pub const SELECTED_COLOR: Color = Color::from_rgb(0.2, 0.4, 1.);
+pub const HIGHLIGHT_COLOR: Color = Color::from_rgb(1., 0.55, 0.);
+pub const HIT_TOLERANCE: f32 = 5.; // px
+const HIT_TEST_SUBDIVISION: usize = 64;
+
+pub const LABEL_FONT_SIZE: f32 = 12.;
+const LABEL_PADDING: f32 = 3.; // px
+const LABEL_BACKGROUND: Color = Color::from_rgba(1., 1., 1., 0.85);
+const ARROW_SIZE: f32 = 8.; // px, grows with the width of the link
+pub const LINK_COLORS: [u32; 5] = [0x000000, 0xcc3333, 0x339940, 0x8c4dbf, 0x737373]; // RGB
+pub const LINK_WIDTHS: [f32; 4] = [1., 2., 3., 4.];
+
+pub fn color_to_hex(color: Color) -> u32 {
+    let component = |value: f32| (value.clamp(0., 1.) * 255.).round() as u32;
+    component(color.r()) << 16 | component(color.g()) << 8 | component(color.b())
+}
+
+// Value following `current` in `values`, the first one when `current` isn't one of them
+pub fn next_in<T: Copy + PartialEq>(values: &[T], current: T) -> T {
+    let index = values.iter().position(|value| *value == current).map_or(0, |index| (index + 1) % values.len());
+    values[index]
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrowShape {
+    #[default]
+    None,
+    Triangle,
+    Open, // Two strokes
+    Diamond,
+    Circle,
+}
+
+impl ArrowShape {
+    pub fn next(self) -> Self {
+        match self {
+            ArrowShape::None => ArrowShape::Triangle,
+            ArrowShape::Triangle => ArrowShape::Open,
+            ArrowShape::Open => ArrowShape::Diamond,
+            ArrowShape::Diamond => ArrowShape::Circle,
+            ArrowShape::Circle => ArrowShape::None,
+        }
+    }
+
+    // `tip` is in screen coordinates and `direction` is the normalized direction the arrow points to
+    fn draw(&self, tip: Vector2<f32>, direction: Vector2<f32>, size: f32, color: Color, graphics: &mut Graphics2D) {
+        let normal = Vector2::new(-direction.y, direction.x) * (size * 0.4);
+        let base = tip - direction * size;
+        match self {
+            ArrowShape::None => {}
+            ArrowShape::Triangle => graphics.draw_triangle([tip, base + normal, base - normal], color),
+            ArrowShape::Open => {
+                graphics.draw_line(tip, base + normal, 1.5, color);
+                graphics.draw_line(tip, base - normal, 1.5, color);
+            }
+            ArrowShape::Diamond => {
+                let (middle, back) = (tip - direction * (size * 0.6), tip - direction * (size * 1.2));
+                graphics.draw_triangle([tip, middle + normal, middle - normal], color);
+                graphics.draw_triangle([back, middle + normal, middle - normal], color);
+            }
+            ArrowShape::Circle => graphics.draw_circle(tip - direction * (size * 0.35), size * 0.35, color),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Dash {
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+impl Dash {
+    pub fn next(self) -> Self {
+        match self {
+            Dash::Solid => Dash::Dashed,
+            Dash::Dashed => Dash::Dotted,
+            Dash::Dotted => Dash::Solid,
+        }
+    }
+
+    // Lengths of the drawn and skipped parts in px
+    pub fn pattern(&self) -> &'static [f32] {
+        match self {
+            Dash::Solid => &[],
+            Dash::Dashed => &[8., 5.],
+            Dash::Dotted => &[2., 4.],
+        }
+    }
+}
+
+// Which links can be created
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct LinkRules {
+    pub allow_self_loops: bool,
+    pub allow_duplicates: bool, // Several links between the same ports
+}
 
-#[derive(Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LinkRefusal {
+    MissingPort,
+    SelfLoop,
+    Duplicate,
+    IncompatibleTypes,
+}
+
+impl LinkRules {
+    pub fn check(&self, graph: &Graph, from: BlockId, from_port: usize, to: BlockId, to_port: usize) -> Result<(), LinkRefusal> {
+        let output = graph.block(from).and_then(|block| block.outputs.get(from_port)).ok_or(LinkRefusal::MissingPort)?;
+        let input = graph.block(to).and_then(|block| block.inputs.get(to_port)).ok_or(LinkRefusal::MissingPort)?;
+        if from == to && !self.allow_self_loops { return Err(LinkRefusal::SelfLoop); }
+        let is_duplicate = graph.links().any(|link| link.from == from && link.from_port == from_port && link.to == to && link.to_port == to_port);
+        if is_duplicate && !self.allow_duplicates { return Err(LinkRefusal::Duplicate); }
+        if !output.is_compatible_with(input) { return Err(LinkRefusal::IncompatibleTypes); }
+        Ok(())
+    }
+}
+
+// Connects an output port of a block to an input port of another one
+#[derive(Clone, Derivative)]
+#[derivative(Debug, PartialEq)]
 pub struct Link {
-    pub from: Rc<RefCell<Block>>,
-    pub to: Option<Rc<RefCell<Block>>>,
+    pub id: LinkId,
+    pub from: BlockId,
+    pub from_port: usize, // Index in the outputs of `from`
+    pub to: BlockId,
+    pub to_port: usize, // Index in the inputs of `to`
+    pub style: LinkStyle,
+    pub start_arrow: ArrowShape,
+    pub end_arrow: ArrowShape,
+    pub color: Color,
+    pub width: f32, // px
+    pub dash: Dash,
+    pub label: String, // Displayed at the middle of the link
+    pub is_selected: bool,
+    #[derivative(Debug = "ignore", PartialEq = "ignore")]
+    label_layout: RefCell<(f32, String, Option<Rc<FormattedTextBlock>>)>, // Label at the current zoom level
 }
 
 impl Link {
-    pub fn new(block: Rc<RefCell<Block>>) -> Self {
+    pub fn new(id: LinkId, from: BlockId, from_port: usize, to: BlockId, to_port: usize) -> Self {
         Self {
-            from: block,
-            to: None
-        }
-    }
-
-    pub fn to(&mut self, to: Rc<RefCell<Block>>) {
-        if Rc::ptr_eq(&to, &self.from) { return; }
-        self.to = Some(to);
-    }
-
-    pub fn render(&self, mouse_pos: Vector2<f32>, graphics: &mut Graphics2D) {
-        let from_block = self.from.borrow();
-        let virtual_mouse_block = Block::new_sized(mouse_pos, 0., 0.); // Virtual block representing the cursor
-        let virtual_mouse_ref= Rc::new(RefCell::new(virtual_mouse_block));
-        let mut to_block = self.to.as_ref().unwrap_or(&virtual_mouse_ref).borrow();
-        let dist = from_block.pos.x - to_block.pos.x;
-        let offset = dist.abs() / 2.;
-        let start = if dist > 0. { Ref::clone(&to_block) } else { Ref::clone(&from_block) };
-        graphics.draw_circle(start.pos, 5., Color::GREEN); // DEBUG
-        let end = if start.pos == from_block.pos { Ref::clone(&to_block) } else { Ref::clone(&from_block) };
-        draw_bezier_curve(
-            start.pos + Vector2::new(start.width, start.height / 2.),
-            start.pos + Vector2::new(offset + start.width, start.height / 2.).mul(0.8), // control 1
-            end.pos + Vector2::new(-offset, end.height / 2.).mul(0.8), // control 2
-            end.pos + Vector2::new(0., end.height / 2.),
-            graphics
-        );
-    }
-}
\ No newline at end of file
+            id,
+            from,
+            from_port,
+            to,
+            to_port,
+            style: LinkStyle::default(),
+            start_arrow: ArrowShape::None,
+            end_arrow: ArrowShape::Triangle,
+            color: Color::from_hex_rgb(LINK_COLORS[0]),
+            width: LINK_WIDTHS[0],
+            dash: Dash::Solid,
+            label: String::new(),
+            is_selected: false,
+            label_layout: RefCell::new((1., String::new(), None)),
+        }
+    }
+
+    pub fn is_connected_to(&self, block: BlockId, port: PortRef) -> bool {
+        match port.side {
+            PortSide::Input => self.to == block && self.to_port == port.index,
+            PortSide::Output => self.from == block && self.from_port == port.index,
+        }
+    }
+
+    pub fn render(&self, cached: &CachedRoute, is_label_edited: bool, camera: &Camera, graphics: &mut Graphics2D) {
+        let (width, color) = if self.is_selected { (self.width + 1., SELECTED_COLOR) } else { (self.width, self.color) };
+        let points: Vec<Vector2<f32>> = cached.polyline.iter().map(|point| camera.to_screen(*point)).collect();
+        draw_polyline(&points, width, color, self.dash.pattern(), graphics);
+        let (start, end) = cached.route.endpoints();
+        let (start_direction, end_direction) = cached.route.tangents();
+        let arrow_size = ARROW_SIZE + 2. * width;
+        self.start_arrow.draw(camera.to_screen(start), Vector2::ZERO - start_direction, arrow_size, color, graphics);
+        self.end_arrow.draw(camera.to_screen(end), end_direction, arrow_size, color, graphics);
+        if self.label.is_empty() || is_label_edited { return; }
+        let mut label_layout = self.label_layout.borrow_mut();
+        if label_layout.0 != camera.zoom || label_layout.1 != self.label || label_layout.2.is_none() {
+            *label_layout = (camera.zoom, self.label.clone(), Some(layout_text(&self.label, LABEL_FONT_SIZE * camera.zoom)));
+        }
+        let Some(layout) = &label_layout.2 else { return; };
+        let center = polyline_midpoint(&points);
+        let origin = center - Vector2::new(layout.width(), layout.height()) / 2.;
+        draw_rectangle(origin.x - LABEL_PADDING, origin.y, layout.width() + 2. * LABEL_PADDING, layout.height(), LABEL_BACKGROUND, graphics);
+        graphics.draw_text(origin, color, layout);
+    }
+
+    // Position of the ports
+    pub fn endpoints(&self, from_block: &Block, to_block: &Block) -> (Vector2<f32>, Vector2<f32>) {
+        (
+            from_block.port_position(PortRef::new(PortSide::Output, self.from_port)),
+            to_block.port_position(PortRef::new(PortSide::Input, self.to_port)),
+        )
+    }
+
+    // Path between the ports in world coordinates, the orthogonal routes go around the `obstacles`
+    pub fn route(&self, from_block: &Block, to_block: &Block, obstacles: &[Rectangle]) -> Route {
+        let (start, end) = self.endpoints(from_block, to_block);
+        match self.style {
+            LinkStyle::Bezier => Route::bezier(start, end),
+            LinkStyle::Straight => Route::Polyline(vec![start, end]),
+            LinkStyle::Orthogonal => Route::Polyline(orthogonal_route(start, end, &from_block.bounds(), &to_block.bounds(), obstacles)),
+        }
+    }
+
+    pub fn distance_to(route: &Route, point: Vector2<f32>) -> f32 {
+        route.distance_to(point, HIT_TEST_SUBDIVISION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::dimen::Vector2;
+
+    use crate::block::Block;
+    use crate::graph::Graph;
+    use crate::link::{LinkRefusal, LinkRules};
+    use crate::port::Port;
+
+    #[test]
+    fn self_loops_and_duplicates_follow_the_rules() {
+        let mut graph = Graph::new();
+        let mut block = Block::new(Vector2::new(0., 0.));
+        (block.inputs, block.outputs) = (vec![Port::any()], vec![Port::any()]);
+        let a = graph.add_block(block.clone());
+        let b = graph.add_block(block);
+        graph.add_link(a, 0, b, 0);
+        let strict = LinkRules::default();
+        assert_eq!(strict.check(&graph, a, 0, a, 0), Err(LinkRefusal::SelfLoop));
+        assert_eq!(strict.check(&graph, a, 0, b, 0), Err(LinkRefusal::Duplicate));
+        assert_eq!(strict.check(&graph, b, 0, a, 0), Ok(()));
+        let lenient = LinkRules { allow_self_loops: true, allow_duplicates: true };
+        assert_eq!(lenient.check(&graph, a, 0, a, 0), Ok(()));
+        assert_eq!(lenient.check(&graph, a, 0, b, 0), Ok(()));
+    }
+
+    #[test]
+    fn typed_ports_refuse_other_types() {
+        let mut graph = Graph::new();
+        let mut from = Block::new(Vector2::new(0., 0.));
+        from.outputs = vec![Port::new("text", "string"), Port::new("count", "number"), Port::any()];
+        let mut to = Block::new(Vector2::new(200., 0.));
+        to.inputs = vec![Port::new("value", "number")];
+        let (from, to) = (graph.add_block(from), graph.add_block(to));
+        let rules = LinkRules::default();
+        assert_eq!(rules.check(&graph, from, 0, to, 0), Err(LinkRefusal::IncompatibleTypes));
+        assert_eq!(rules.check(&graph, from, 1, to, 0), Ok(()));
+        assert_eq!(rules.check(&graph, from, 2, to, 0), Ok(()));
+    }
+}
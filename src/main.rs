@@ -1,45 +1,88 @@
-mod context;
-mod animation;
-mod block;
-mod link;
-mod render_helper;
-
-
-#[macro_use]
-extern crate derivative;
-extern crate core;
-
 use std::thread;
 use std::env;
-use std::ffi::OsStr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::time::{Duration, Instant};
 
 use speedy2d::color::Color;
 use speedy2d::dimen::Vector2;
-use speedy2d::window::{KeyScancode, ModifiersState, MouseButton, VirtualKeyCode, WindowCreationOptions, WindowHandler, WindowHelper, WindowPosition, WindowSize, WindowStartupInfo};
+use speedy2d::window::{KeyScancode, ModifiersState, MouseButton, MouseScrollDistance, VirtualKeyCode, WindowCreationOptions, WindowHandler, WindowHelper, WindowPosition, WindowSize, WindowStartupInfo};
 use speedy2d::{Graphics2D, Window};
 
-use crate::context::Context;
+use block_one::AppEvent;
+use block_one::align::{Alignment, Distribution};
+use block_one::context::Context;
+use block_one::document::{DOCUMENT_EXTENSION, DocumentError};
 
 const FPS: u64 = 60;
 const FRAME_DURATION: u64 = 1000 / FPS; // ms
 
-#[derive(PartialEq, Debug, Clone)]
-pub enum AppEvent {
-    Update,
-    Redraw,
-}
-
 struct AppWindowHandler {
     context: Context,
     tick_timestamp: Instant,
     mouse_button_pressed: (bool, bool), // (Left, Right)
 }
 
+impl AppWindowHandler {
+    // Cmd on macOS, Ctrl elsewhere
+    fn is_command_pressed(&self) -> bool {
+        self.context.modifiers.ctrl() || self.context.modifiers.logo()
+    }
+
+    fn save(&mut self, helper: &mut WindowHelper<AppEvent>, save_as: bool) {
+        let path = match &self.context.path {
+            Some(path) if !save_as => path.clone(),
+            _ => {
+                let default_path = format!("untitled.{}", DOCUMENT_EXTENSION);
+                let filter = format!("*.{}", DOCUMENT_EXTENSION);
+                match tinyfiledialogs::save_file_dialog_with_filter("Save diagram", &default_path, &[&filter], "Block One diagram") {
+                    Some(path) => PathBuf::from(path),
+                    None => return,
+                }
+            }
+        };
+        match self.context.save(&path) {
+            Ok(()) => set_app_title(helper, &path),
+            Err(e) => show_error("Unable to save the diagram", &e),
+        }
+    }
+
+    fn on_editor_key_down(&mut self, virtual_key_code: Option<VirtualKeyCode>) {
+        let shift = self.context.modifiers.shift();
+        let Some(editor) = &mut self.context.editor else { return; };
+        match virtual_key_code {
+            Some(VirtualKeyCode::Escape) => self.context.commit_edition(),
+            Some(VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter) if editor.is_single_line() => self.context.commit_edition(),
+            Some(VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter) => editor.insert("\n"),
+            Some(VirtualKeyCode::Backspace) => editor.backspace(),
+            Some(VirtualKeyCode::Delete) => editor.delete(),
+            Some(VirtualKeyCode::Left) => editor.move_left(shift),
+            Some(VirtualKeyCode::Right) => editor.move_right(shift),
+            Some(VirtualKeyCode::Up) => editor.move_up(shift),
+            Some(VirtualKeyCode::Down) => editor.move_down(shift),
+            Some(VirtualKeyCode::Home) => editor.move_to_line_start(shift),
+            Some(VirtualKeyCode::End) => editor.move_to_line_end(shift),
+            _ => {}
+        }
+    }
+
+    fn open(&mut self, helper: &mut WindowHelper<AppEvent>) {
+        let filter = format!("*.{}", DOCUMENT_EXTENSION);
+        let Some(path) = tinyfiledialogs::open_file_dialog("Open diagram", "", Some((&[&filter], "Block One diagram"))) else { return; };
+        let path = PathBuf::from(path);
+        match self.context.open(&path) {
+            Ok(()) => set_app_title(helper, &path),
+            Err(e) => show_error("Unable to open the diagram", &e),
+        }
+    }
+}
+
 impl WindowHandler<AppEvent> for AppWindowHandler {
-    fn on_start(&mut self, helper: &mut WindowHelper<AppEvent>, _info: WindowStartupInfo) {
+    fn on_start(&mut self, helper: &mut WindowHelper<AppEvent>, info: WindowStartupInfo) {
+        self.context.viewport_size = info.viewport_size_pixels().into_f32();
         let event_sender = helper.create_user_event_sender();
+        self.context.event_sender = Some(event_sender.clone());
+        if let Some(path) = &self.context.path { set_app_title(helper, path); }
         helper.request_redraw();
         thread::spawn(move || {
             loop {
@@ -51,6 +94,7 @@ impl WindowHandler<AppEvent> for AppWindowHandler {
 
     #[warn(unreachable_patterns)]
     fn on_user_event(&mut self, helper: &mut WindowHelper<AppEvent>, user_event: AppEvent) {
+        if let Some(debug) = &mut self.context.debug { debug.count_event(&user_event); }
         match user_event {
             AppEvent::Redraw => helper.request_redraw(),
             AppEvent::Update => {
@@ -60,7 +104,9 @@ impl WindowHandler<AppEvent> for AppWindowHandler {
         }
     }
 
-    fn on_resize(&mut self, _helper: &mut WindowHelper<AppEvent>, _size_pixels: Vector2<u32>) {}
+    fn on_resize(&mut self, _helper: &mut WindowHelper<AppEvent>, size_pixels: Vector2<u32>) {
+        self.context.viewport_size = size_pixels.into_f32();
+    }
 
     fn on_draw(&mut self, _helper: &mut WindowHelper<AppEvent>, graphics: &mut Graphics2D) {
         graphics.clear_screen(Color::WHITE);
@@ -68,17 +114,14 @@ impl WindowHandler<AppEvent> for AppWindowHandler {
     }
 
     fn on_mouse_move(&mut self, helper: &mut WindowHelper<AppEvent>, position: Vector2<f32>) {
-        if self.context.drag { self.context.move_block(position); }
-        self.context.mouse_position = position;
+        self.context.on_mouse_move(position);
+        helper.set_cursor_visible(self.context.cursor_handle().is_none());
         helper.request_redraw();
     }
 
     fn on_mouse_button_down(&mut self, helper: &mut WindowHelper<AppEvent>, button: MouseButton) {
         self.context.on_mouse_clicked(button);
-        match button {
-            MouseButton::Left => self.context.drag = true,
-            _ => {}
-        }
+        if button == MouseButton::Left && !self.context.is_editing() { self.context.drag = true; }
         helper.request_redraw();
     }
 
@@ -86,24 +129,76 @@ impl WindowHandler<AppEvent> for AppWindowHandler {
         match button {
             MouseButton::Left => {
                 self.mouse_button_pressed.0 = false;
-                self.context.drag = false;
+                self.context.end_drag();
             },
             MouseButton::Right => self.mouse_button_pressed.1 = false,
+            MouseButton::Middle => self.context.end_drag(),
             _ => ()
         }
     }
 
+    fn on_mouse_wheel_scroll(&mut self, helper: &mut WindowHelper<AppEvent>, distance: MouseScrollDistance) {
+        match distance {
+            MouseScrollDistance::Lines { x, y, .. } => self.context.on_scroll(Vector2::new(x as f32, y as f32), true),
+            MouseScrollDistance::Pixels { x, y, .. } => self.context.on_scroll(Vector2::new(x as f32, y as f32), false),
+            MouseScrollDistance::Pages { x, y, .. } => self.context.on_scroll(Vector2::new(x as f32, y as f32), true),
+        }
+        helper.request_redraw();
+    }
+
     fn on_key_down(&mut self, helper: &mut WindowHelper<AppEvent>, virtual_key_code: Option<VirtualKeyCode>, _scancode: KeyScancode) {
+        if self.context.is_editing() && !self.is_command_pressed() {
+            self.on_editor_key_down(virtual_key_code);
+            self.context.on_edition_changed();
+            helper.request_redraw();
+            return;
+        }
         match virtual_key_code {
-            Some(VirtualKeyCode::Backspace | VirtualKeyCode::Delete) => self.context.delete_focused_block(),
+            Some(VirtualKeyCode::S) if self.is_command_pressed() => self.save(helper, self.context.modifiers.shift()),
+            Some(VirtualKeyCode::O) if self.is_command_pressed() => self.open(helper),
+            Some(VirtualKeyCode::Z) if self.is_command_pressed() && self.context.modifiers.shift() => self.context.redo(),
+            Some(VirtualKeyCode::Z) if self.is_command_pressed() => self.context.undo(),
+            Some(VirtualKeyCode::A) if self.is_command_pressed() => match &mut self.context.editor {
+                Some(editor) => editor.select_all(),
+                None => self.context.select_all(),
+            },
+            Some(VirtualKeyCode::C) if self.is_command_pressed() => self.context.copy_selection(),
+            Some(VirtualKeyCode::X) if self.is_command_pressed() => self.context.cut_selection(),
+            Some(VirtualKeyCode::V) if self.is_command_pressed() => self.context.paste(),
+            Some(VirtualKeyCode::D) if self.is_command_pressed() => self.context.duplicate_selection(),
+            // Alignment of the selection
+            Some(VirtualKeyCode::Left) if self.context.modifiers.alt() => self.context.align(Alignment::Left),
+            Some(VirtualKeyCode::Right) if self.context.modifiers.alt() => self.context.align(Alignment::Right),
+            Some(VirtualKeyCode::Up) if self.context.modifiers.alt() => self.context.align(Alignment::Top),
+            Some(VirtualKeyCode::Down) if self.context.modifiers.alt() => self.context.align(Alignment::Bottom),
+            Some(VirtualKeyCode::H) if self.context.modifiers.alt() && self.context.modifiers.shift() => self.context.distribute(Distribution::Horizontal),
+            Some(VirtualKeyCode::V) if self.context.modifiers.alt() && self.context.modifiers.shift() => self.context.distribute(Distribution::Vertical),
+            Some(VirtualKeyCode::H) if self.context.modifiers.alt() => self.context.align(Alignment::CenterHorizontal),
+            Some(VirtualKeyCode::V) if self.context.modifiers.alt() => self.context.align(Alignment::CenterVertical),
+            Some(VirtualKeyCode::Backspace | VirtualKeyCode::Delete) => self.context.delete_selection(),
+            Some(VirtualKeyCode::Escape) => self.context.on_escape(),
+            Some(VirtualKeyCode::Space) => self.context.space_pressed = true,
+            Some(VirtualKeyCode::F12) => self.context.toggle_debug(),
             _ => {}
         }
         helper.request_redraw();
     }
 
+    fn on_key_up(&mut self, _helper: &mut WindowHelper<AppEvent>, virtual_key_code: Option<VirtualKeyCode>, _scancode: KeyScancode) {
+        if virtual_key_code == Some(VirtualKeyCode::Space) { self.context.space_pressed = false; }
+    }
+
     fn on_keyboard_char(&mut self, helper: &mut WindowHelper<AppEvent>, unicode_codepoint: char) {
-        if unicode_codepoint >= ' '  && unicode_codepoint <= '~' || unicode_codepoint >= '¡' {
-            self.context.on_keydown(unicode_codepoint.to_string());
+        if self.is_command_pressed() { return; } // Shortcuts are handled in `on_key_down`
+        if (' '..='~').contains(&unicode_codepoint) || unicode_codepoint >= '¡' {
+            match &mut self.context.editor {
+                Some(editor) => {
+                    editor.insert(&unicode_codepoint.to_string());
+                    self.context.on_edition_changed();
+                }
+                None if self.context.modifiers.alt() => {} // Alt shortcuts are handled in `on_key_down`
+                None => self.context.on_keydown(unicode_codepoint.to_string()),
+            }
             // match self.focus {
             //     FocusElement::Editor => {
             //         self.editor.add_char(unicode_codepoint.to_string());
@@ -128,11 +223,21 @@ impl WindowHandler<AppEvent> for AppWindowHandler {
         }
     }
 
-    fn on_keyboard_modifiers_changed(&mut self, _helper: &mut WindowHelper<AppEvent>, _state: ModifiersState) { }
+    fn on_keyboard_modifiers_changed(&mut self, _helper: &mut WindowHelper<AppEvent>, state: ModifiersState) {
+        self.context.modifiers = state;
+    }
+}
+
+fn set_app_title(helper: &mut WindowHelper<AppEvent>, path: &Path) {
+    match path.file_name() {
+        Some(filename) => helper.set_title(format!("Block One - {}", filename.to_string_lossy())),
+        None => helper.set_title("Block One"),
+    }
 }
 
-fn set_app_title(helper: &mut WindowHelper<AppEvent>, _path: &str) {
-    helper.set_title("Block One")
+fn show_error(title: &str, error: &DocumentError) {
+    eprintln!("{}: {}", title, error);
+    tinyfiledialogs::message_box_ok(title, &error.to_string(), tinyfiledialogs::MessageBoxIcon::Error);
 }
 
 fn main() {
@@ -147,8 +252,40 @@ fn main() {
             Some(WindowPosition::Center)
         )
     ).unwrap();
-    let context = Context::new();
-    if args.len() > 1 { }
+    let mut context = Context::new();
+    let mut path_arg = None;
+    for arg in args.iter().skip(1) {
+        if let Some(depth) = arg.strip_prefix("--history-depth=") {
+            match depth.parse() {
+                Ok(depth) => context.set_history_depth(depth),
+                Err(_) => {
+                    eprintln!("Invalid history depth: {}", depth);
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--allow-self-loops" {
+            context.link_rules.allow_self_loops = true;
+        } else if arg == "--allow-duplicate-links" {
+            context.link_rules.allow_duplicates = true;
+        } else if arg == "--raise-on-click" {
+            context.raise_on_click = true;
+        } else if arg == "--debug" {
+            context.toggle_debug();
+        } else {
+            path_arg = Some(arg);
+        }
+    }
+    if let Some(path) = path_arg {
+        let path = PathBuf::from(path);
+        if path.exists() {
+            if let Err(e) = context.open(&path) {
+                eprintln!("Unable to open {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        } else {
+            context.path = Some(path); // New document, created on the first save
+        }
+    }
 
     let window_handler = AppWindowHandler {
         context,
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use speedy2d::color::Color;
+
+pub const PORT_RADIUS: f32 = 4.;
+pub const PORT_HIT_RADIUS: f32 = 8.; // px
+pub const PORT_SPACING: f32 = 20.; // Minimum vertical distance between two ports
+pub const PORT_FONT_SIZE: f32 = 11.;
+pub const ANY_TYPE: &str = "any";
+pub const PORT_COLOR: Color = Color::from_rgb(0.35, 0.35, 0.35);
+pub const PORT_HOVER_COLOR: Color = Color::from_rgb(0.2, 0.4, 1.);
+pub const PORT_REFUSED_COLOR: Color = Color::from_rgb(0.9, 0.15, 0.15);
+
+// Links leave a block by an output port (right side) and enter by an input port (left side)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PortSide {
+    Input,
+    Output,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PortRef {
+    pub side: PortSide,
+    pub index: usize,
+}
+
+impl PortRef {
+    pub fn new(side: PortSide, index: usize) -> Self {
+        Self { side, index }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Port {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_tag: String,
+}
+
+impl Port {
+    pub fn new(name: &str, type_tag: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            type_tag: type_tag.to_string(),
+        }
+    }
+
+    pub fn any() -> Self {
+        Self::new("", ANY_TYPE)
+    }
+
+    // "name: type" as typed by the user, the type is omitted when the port accepts anything
+    pub fn from_label(label: &str) -> Self {
+        match label.split_once(':') {
+            Some((name, type_tag)) if !type_tag.trim().is_empty() => Self::new(name.trim(), type_tag.trim()),
+            Some((name, _)) => Self::new(name.trim(), ANY_TYPE),
+            None => Self::new(label.trim(), ANY_TYPE),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        if self.type_tag == ANY_TYPE { return self.name.clone(); }
+        format!("{}: {}", self.name, self.type_tag)
+    }
+
+    // Data of this (output) port can flow into the `input` port
+    pub fn is_compatible_with(&self, input: &Port) -> bool {
+        self.type_tag == ANY_TYPE || input.type_tag == ANY_TYPE || self.type_tag == input.type_tag
+    }
+}
+
+// Ports of the blocks created before the ports were typed
+pub fn default_ports() -> Vec<Port> {
+    vec![Port::any()]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::port::{ANY_TYPE, Port};
+
+    #[test]
+    fn labels_give_the_name_and_the_type() {
+        assert_eq!(Port::from_label(" count : number"), Port::new("count", "number"));
+        assert_eq!(Port::from_label("value:"), Port::new("value", ANY_TYPE));
+        assert_eq!(Port::from_label("in0"), Port::new("in0", ANY_TYPE));
+        for port in [Port::new("count", "number"), Port::new("in0", ANY_TYPE)] { assert_eq!(Port::from_label(&port.label()), port); }
+    }
+}
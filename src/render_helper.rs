@@ -2,7 +2,9 @@ use std::ops::Mul;
 use speedy2d::color::Color;
 use speedy2d::dimen::Vector2;
 use speedy2d::Graphics2D;
-use speedy2d::shape::{Polygon, Rectangle};
+use speedy2d::shape::Rectangle;
+
+use crate::geometry::{distance_to_segment, dot};
 
 #[inline]
 pub fn draw_rounded_rectangle(x: f32, y: f32, width: f32, height: f32, radius: f32, color: Color, graphics: &mut Graphics2D) {
@@ -20,7 +22,7 @@ pub fn draw_rectangle(x: f32, y: f32, width: f32, height: f32, color: Color, gra
 }
 
 #[inline]
-pub fn _draw_rect_border(origin: Vector2<f32>, width: f32, height: f32, thickness: f32, border_color: Color, graphics: &mut Graphics2D) {
+pub fn draw_rect_border(origin: Vector2<f32>, width: f32, height: f32, thickness: f32, border_color: Color, graphics: &mut Graphics2D) {
     graphics.draw_line(origin, origin + Vector2::new(width, 0.), thickness, border_color);
     graphics.draw_line(origin + Vector2::new(width, 0.), origin + Vector2::new(width, height), thickness, border_color);
     graphics.draw_line(origin + Vector2::new(width, height), origin + Vector2::new(0., height), thickness, border_color);
@@ -29,6 +31,7 @@ pub fn _draw_rect_border(origin: Vector2<f32>, width: f32, height: f32, thicknes
 
 
 #[inline]
+#[allow(clippy::too_many_arguments)]
 pub fn draw_rounded_rectangle_with_border(x: f32, y: f32, width: f32, height: f32, radius: f32, border_width: f32, bg_color: Color, border_color: Color, graphics: &mut Graphics2D) {
     // draw border
     draw_rounded_rectangle(x - border_width, y - border_width, width + 2. * border_width, height + 2. * border_width, radius - border_width, border_color, graphics);
@@ -37,6 +40,7 @@ pub fn draw_rounded_rectangle_with_border(x: f32, y: f32, width: f32, height: f3
 }
 
 #[inline]
+#[allow(dead_code)]
 pub fn draw_rounded_line(x: f32, y: f32, width: f32, height: f32, color: Color, graphics: &mut Graphics2D) {
     let radius= width / 2.;
     graphics.draw_circle(Vector2::new(x + radius, y + radius), radius, color);
@@ -47,24 +51,118 @@ pub fn draw_rounded_line(x: f32, y: f32, width: f32, height: f32, color: Color,
 
 type Point = Vector2<f32>;
 
+pub const FLATNESS_TOLERANCE: f32 = 0.25; // px, max distance between a curve and its flattened polyline
+const MAX_FLATTENING_DEPTH: usize = 10;
+const FEATHER: f32 = 1.; // px
+const MITER_LIMIT: f32 = 4.; // Relative to the half width of the stroke
+
+#[inline]
+pub fn bezier_point(start: Point, control1: Point, control2: Point, end: Point, t: f32) -> Point {
+    start.mul((1.-t).powf(3.)) + control1.mul(3.*(1.-t).powf(2.)*t) + control2.mul(3.*(1.-t)*t.powf(2.)) + end.mul(t.powf(3.)) // Bezier polynom
+}
+
+// Derivative of the Bezier polynom, the direction of the curve at `t`
 #[inline]
-pub fn draw_bezier_curve(start: Point, control1: Point, control2: Point, end: Point, graphics: &mut Graphics2D) {
-    let nb_subdivision = 100;
+pub fn bezier_tangent(start: Point, control1: Point, control2: Point, end: Point, t: f32) -> Point {
+    (control1 - start).mul(3.*(1.-t).powf(2.)) + (control2 - control1).mul(6.*(1.-t)*t) + (end - control2).mul(3.*t.powf(2.))
+}
+
+// Subdivide the curve until each piece is within `tolerance` of a straight segment, so long or zoomed
+// curves get more points and short or flat ones fewer
+pub fn flatten_bezier(start: Point, control1: Point, control2: Point, end: Point, tolerance: f32) -> Vec<Point> {
     let mut points = vec![start];
-    // DEBUG
-    graphics.draw_circle(start, 2., Color::RED);
-    graphics.draw_circle(control1, 2., Color::RED);
-    graphics.draw_circle(control2, 2., Color::RED);
-    graphics.draw_circle(end, 2., Color::RED);
-    graphics.draw_line(start, control1, 0.5, Color::CYAN);
-    graphics.draw_line(control1, control2, 0.5, Color::CYAN);
-    graphics.draw_line(control2, end, 0.5, Color::CYAN);
-    for i in 0 .. nb_subdivision {
-        let t = (i as f32 + 1.) / nb_subdivision as f32;
-        assert!(0. <= t && t <= 1.);
-        let new_point = start.mul((1.-t).powf(3.)) + control1.mul(3.*(1.-t).powf(2.)*t) + control2.mul(3.*(1.-t)*t.powf(2.)) + end.mul(t.powf(3.)); // Bezier polynom
-        if i >= 1 { graphics.draw_line(points[i-1], points[i], 1., Color::BLACK); } // draw the curve
-        points.push(new_point);
+    flatten_bezier_into(start, control1, control2, end, tolerance, 0, &mut points);
+    points
+}
+
+fn flatten_bezier_into(start: Point, control1: Point, control2: Point, end: Point, tolerance: f32, depth: usize, points: &mut Vec<Point>) {
+    let flatness = distance_to_segment(control1, start, end).max(distance_to_segment(control2, start, end));
+    if flatness <= tolerance || depth >= MAX_FLATTENING_DEPTH {
+        points.push(end);
+        return;
+    }
+    // de Casteljau split at the middle of the curve
+    let (a, b, c) = ((start + control1) / 2., (control1 + control2) / 2., (control2 + end) / 2.);
+    let (d, e) = ((a + b) / 2., (b + c) / 2.);
+    let middle = (d + e) / 2.;
+    flatten_bezier_into(start, a, d, middle, tolerance, depth + 1, points);
+    flatten_bezier_into(middle, e, c, end, tolerance, depth + 1, points);
+}
+
+// `dash` alternates the lengths of the drawn and skipped parts, the line is solid when it is empty
+// The triangles of all the dashes are built first and then drawn together
+pub fn draw_polyline(points: &[Point], width: f32, color: Color, dash: &[f32], graphics: &mut Graphics2D) {
+    let mut triangles = vec![];
+    if dash.is_empty() {
+        stroke_triangles(points, width, color, &mut triangles);
+        return draw_triangles(&triangles, graphics);
     }
-    graphics.draw_line(points.last().unwrap(), end, 1., Color::BLACK);
-}
\ No newline at end of file
+    let (mut index, mut remaining) = (0, dash[0]); // Current part of the pattern and its length left to draw or skip
+    let mut dash_points = vec![];
+    for segment in points.windows(2) {
+        let (mut from, to) = (segment[0], segment[1]);
+        let mut length = (to - from).magnitude();
+        while length > 0. {
+            let step = remaining.min(length);
+            let next = from + (to - from).mul(step / length);
+            if index % 2 == 0 {
+                if dash_points.is_empty() { dash_points.push(from); }
+                dash_points.push(next);
+            }
+            (from, length, remaining) = (next, length - step, remaining - step);
+            if remaining <= 0. {
+                stroke_triangles(&dash_points, width, color, &mut triangles);
+                dash_points.clear();
+                index = (index + 1) % dash.len();
+                remaining = dash[index];
+            }
+        }
+    }
+    stroke_triangles(&dash_points, width, color, &mut triangles);
+    draw_triangles(&triangles, graphics);
+}
+
+// speedy2d has no API to submit a vertex buffer: each triangle is queued on its own,
+// the queue is then uploaded and drawn in a single batch at the end of the frame
+fn draw_triangles(triangles: &[(Point, Color)], graphics: &mut Graphics2D) {
+    for triangle in triangles.chunks_exact(3) {
+        graphics.draw_triangle_three_color([triangle[0].0, triangle[1].0, triangle[2].0], [triangle[0].1, triangle[1].1, triangle[2].1]);
+    }
+}
+
+// Quads along the points with mitered joins, the edges fade out over `FEATHER` px to be anti-aliased
+// Appends two triangles per quad, as three (position, color) vertices each
+fn stroke_triangles(points: &[Point], width: f32, color: Color, triangles: &mut Vec<(Point, Color)>) {
+    let mut points = points.to_vec();
+    points.dedup_by(|a, b| (*a - *b).magnitude_squared() < 1e-6);
+    if points.len() < 2 { return; }
+    let normals: Vec<Point> = points.windows(2)
+        .map(|segment| (segment[1] - segment[0]).normalize().map_or(Vector2::ZERO, |direction| Vector2::new(-direction.y, direction.x)))
+        .collect();
+    let offsets: Vec<Point> = (0..points.len()).map(|i| {
+        let (before, after) = (normals[i.saturating_sub(1)], normals[i.min(normals.len() - 1)]);
+        let miter = (before + after).normalize().unwrap_or(after);
+        miter / dot(miter, after).max(1. / MITER_LIMIT)
+    }).collect();
+    let (inner, outer) = ((width - FEATHER).max(0.) / 2., (width + FEATHER) / 2.);
+    let transparent = Color::from_rgba(color.r(), color.g(), color.b(), 0.);
+    let mut quad = |vertices: [Point; 4], colors: [Color; 4]| {
+        triangles.extend([0, 1, 2, 0, 2, 3].map(|i| (vertices[i], colors[i])));
+    };
+    for i in 0..points.len() - 1 {
+        let (a, b, offset_a, offset_b) = (points[i], points[i + 1], offsets[i], offsets[i + 1]);
+        if inner > 0. { quad([a + offset_a * inner, b + offset_b * inner, b - offset_b * inner, a - offset_a * inner], [color; 4]); }
+        for side in [1., -1.] {
+            let (offset_a, offset_b) = (offset_a * side, offset_b * side);
+            quad([a + offset_a * outer, b + offset_b * outer, b + offset_b * inner, a + offset_a * inner], [transparent, transparent, color, color]);
+        }
+    }
+}
+
+// Flattened in screen coordinates, used for the curves which aren't cached
+#[inline]
+#[allow(clippy::too_many_arguments)]
+pub fn draw_bezier_curve(start: Point, control1: Point, control2: Point, end: Point, width: f32, color: Color, dash: &[f32], graphics: &mut Graphics2D) {
+    let points = flatten_bezier(start, control1, control2, end, FLATNESS_TOLERANCE);
+    draw_polyline(&points, width, color, dash, graphics);
+}
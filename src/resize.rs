@@ -0,0 +1,235 @@
+use speedy2d::color::Color;
+use speedy2d::dimen::Vector2;
+use speedy2d::Graphics2D;
+use speedy2d::shape::Rectangle;
+
+use crate::block::{Block, MIN_HEIGHT, MIN_WIDTH};
+use crate::graph::BlockId;
+use crate::grid::Grid;
+use crate::render_helper::{draw_rectangle, draw_rounded_rectangle};
+
+const HANDLE_SIZE: f32 = 7.;
+const HANDLE_HIT_TOLERANCE: f32 = 6.;
+const HANDLE_MARGIN: f32 = 10.; // Keeps the handles away from the ports on the block edges
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ResizeHandle {
+    TopLeft,
+    Top,
+    TopRight,
+    Right,
+    BottomRight,
+    Bottom,
+    BottomLeft,
+    Left,
+}
+
+impl ResizeHandle {
+    const ALL: [ResizeHandle; 8] = [
+        ResizeHandle::TopLeft, ResizeHandle::Top, ResizeHandle::TopRight, ResizeHandle::Right,
+        ResizeHandle::BottomRight, ResizeHandle::Bottom, ResizeHandle::BottomLeft, ResizeHandle::Left,
+    ];
+
+    // Which side of the bounds is moved by the handle: -1 for left/top, 1 for right/bottom, 0 for none
+    fn direction(&self) -> (f32, f32) {
+        match self {
+            ResizeHandle::TopLeft => (-1., -1.),
+            ResizeHandle::Top => (0., -1.),
+            ResizeHandle::TopRight => (1., -1.),
+            ResizeHandle::Right => (1., 0.),
+            ResizeHandle::BottomRight => (1., 1.),
+            ResizeHandle::Bottom => (0., 1.),
+            ResizeHandle::BottomLeft => (-1., 1.),
+            ResizeHandle::Left => (-1., 0.),
+        }
+    }
+
+    fn position(&self, bounds: &Rectangle) -> Vector2<f32> {
+        let (dx, dy) = self.direction();
+        let center = (*bounds.top_left() + *bounds.bottom_right()) / 2.;
+        center + Vector2::new(dx * bounds.width() / 2., dy * bounds.height() / 2.)
+    }
+
+    // `bounds` are the screen bounds of the resized blocks
+    pub fn at(bounds: &Rectangle, pos: Vector2<f32>) -> Option<Self> {
+        let bounds = handles_bounds(bounds);
+        Self::ALL.into_iter().find(|handle| {
+            let handle_pos = handle.position(&bounds);
+            (handle_pos.x - pos.x).abs() <= HANDLE_HIT_TOLERANCE && (handle_pos.y - pos.y).abs() <= HANDLE_HIT_TOLERANCE
+        })
+    }
+}
+
+fn handles_bounds(bounds: &Rectangle) -> Rectangle {
+    let margin = Vector2::new(HANDLE_MARGIN, HANDLE_MARGIN);
+    Rectangle::new(*bounds.top_left() - margin, *bounds.bottom_right() + margin)
+}
+
+// Bounding box of a set of blocks
+pub fn blocks_bounds<'a>(blocks: impl Iterator<Item = &'a Block>) -> Option<Rectangle> {
+    blocks.fold(None, |bounds: Option<Rectangle>, block| {
+        let (top_left, bottom_right) = (block.pos, block.pos + Vector2::new(block.width, block.height));
+        Some(match bounds {
+            Some(bounds) => Rectangle::new(
+                Vector2::new(bounds.top_left().x.min(top_left.x), bounds.top_left().y.min(top_left.y)),
+                Vector2::new(bounds.bottom_right().x.max(bottom_right.x), bounds.bottom_right().y.max(bottom_right.y)),
+            ),
+            None => Rectangle::new(top_left, bottom_right),
+        })
+    })
+}
+
+// A resize of the focused blocks, they are scaled proportionally to their bounding box
+pub struct Resize {
+    pub handle: ResizeHandle,
+    pub origin: Vector2<f32>, // Mouse position at the start of the resize
+    pub before: Vec<Block>,
+    bounds: Rectangle,
+    min_size: Vector2<f32>,
+}
+
+impl Resize {
+    pub fn new(handle: ResizeHandle, origin: Vector2<f32>, blocks: Vec<Block>) -> Option<Self> {
+        let bounds = blocks_bounds(blocks.iter())?;
+        // The bounds can't shrink more than the smallest allowed scale of each block
+        let min_scale = blocks.iter().fold(Vector2::new(0f32, 0f32), |scale, block| {
+            Vector2::new(scale.x.max(MIN_WIDTH.min(block.width) / block.width), scale.y.max(MIN_HEIGHT.min(block.height) / block.height))
+        });
+        Some(Self {
+            handle,
+            origin,
+            min_size: Vector2::new(bounds.width() * min_scale.x, bounds.height() * min_scale.y),
+            bounds,
+            before: blocks,
+        })
+    }
+
+    // Mouse position moving the dragged edges onto the grid
+    pub fn snap_mouse(&self, mouse_position: Vector2<f32>, grid: &Grid) -> Vector2<f32> {
+        let handle_position = self.handle.position(&self.bounds);
+        self.origin + grid.snap_point(handle_position + mouse_position - self.origin) - handle_position
+    }
+
+    pub fn ids(&self) -> Vec<BlockId> {
+        self.before.iter().map(|block| block.id).collect()
+    }
+
+    pub fn resized_bounds(&self, mouse_position: Vector2<f32>, keep_ratio: bool) -> Rectangle {
+        let (dx, dy) = self.handle.direction();
+        let delta = mouse_position - self.origin;
+        let (width, height) = (self.bounds.width(), self.bounds.height());
+        let mut new_width = (width + dx * delta.x).max(self.min_size.x);
+        let mut new_height = (height + dy * delta.y).max(self.min_size.y);
+        if keep_ratio {
+            let scale = match (dx != 0., dy != 0.) {
+                (true, true) => (new_width / width).max(new_height / height),
+                (true, false) => new_width / width,
+                _ => new_height / height,
+            };
+            let scale = scale.max(self.min_size.x / width).max(self.min_size.y / height);
+            new_width = width * scale;
+            new_height = height * scale;
+        }
+        // The side opposite to the handle stays in place
+        let x = if dx < 0. { self.bounds.bottom_right().x - new_width } else { self.bounds.top_left().x };
+        let y = if dy < 0. { self.bounds.bottom_right().y - new_height } else { self.bounds.top_left().y };
+        Rectangle::new(Vector2::new(x, y), Vector2::new(x + new_width, y + new_height))
+    }
+
+    // The blocks scaled to the new bounds
+    pub fn apply(&self, bounds: &Rectangle) -> Vec<Block> {
+        let scale = Vector2::new(bounds.width() / self.bounds.width(), bounds.height() / self.bounds.height());
+        self.before.iter().map(|block| {
+            let mut block = block.clone();
+            let offset = block.pos - *self.bounds.top_left();
+            block.pos = *bounds.top_left() + Vector2::new(offset.x * scale.x, offset.y * scale.y);
+            block.width *= scale.x;
+            block.height *= scale.y;
+            block
+        }).collect()
+    }
+}
+
+pub fn render_handles(bounds: &Rectangle, hovered: Option<ResizeHandle>, graphics: &mut Graphics2D) {
+    let bounds = handles_bounds(bounds);
+    for handle in ResizeHandle::ALL {
+        let pos = handle.position(&bounds) - Vector2::new(HANDLE_SIZE / 2., HANDLE_SIZE / 2.);
+        let color = if Some(handle) == hovered { Color::from_rgb(0.2, 0.4, 1.) } else { Color::WHITE };
+        draw_rectangle(pos.x - 1., pos.y - 1., HANDLE_SIZE + 2., HANDLE_SIZE + 2., Color::BLACK, graphics);
+        draw_rectangle(pos.x, pos.y, HANDLE_SIZE, HANDLE_SIZE, color, graphics);
+    }
+}
+
+// Replace the system cursor (which can't be changed) by a double arrow oriented like the handle
+pub fn render_resize_cursor(handle: ResizeHandle, pos: Vector2<f32>, graphics: &mut Graphics2D) {
+    let (dx, dy) = handle.direction();
+    let direction = Vector2::new(dx, dy).normalize().unwrap_or(Vector2::new(1., 0.));
+    let normal = Vector2::new(-direction.y, direction.x);
+    let (tip1, tip2) = (pos + direction * 9., pos - direction * 9.);
+    graphics.draw_line(tip1, tip2, 2., Color::BLACK);
+    for (tip, sign) in [(tip1, 1.), (tip2, -1.)] {
+        let base = tip - direction * (5. * sign);
+        graphics.draw_triangle([tip, base + normal * 4., base - normal * 4.], Color::BLACK);
+    }
+    draw_rounded_rectangle(pos.x - 1.5, pos.y - 1.5, 3., 3., 1.5, Color::WHITE, graphics);
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::dimen::Vector2;
+
+    use crate::block::{Block, MIN_HEIGHT, MIN_WIDTH};
+    use crate::resize::{Resize, ResizeHandle};
+
+    fn resize(handle: ResizeHandle, blocks: Vec<Block>) -> Resize {
+        Resize::new(handle, Vector2::ZERO, blocks).unwrap()
+    }
+
+    #[test]
+    fn the_opposite_side_stays_in_place() {
+        let resize = resize(ResizeHandle::TopLeft, vec![Block::new_sized(Vector2::new(100., 100.), 200., 100.)]);
+        let bounds = resize.resized_bounds(Vector2::new(50., -20.), false);
+        assert_eq!((*bounds.top_left(), *bounds.bottom_right()), (Vector2::new(150., 80.), Vector2::new(300., 200.)));
+        let resize = Resize { handle: ResizeHandle::Right, ..resize };
+        let bounds = resize.resized_bounds(Vector2::new(50., -20.), false);
+        assert_eq!((*bounds.top_left(), *bounds.bottom_right()), (Vector2::new(100., 100.), Vector2::new(350., 200.)));
+    }
+
+    #[test]
+    fn each_block_keeps_its_minimum_size() {
+        let small = Block::new_sized(Vector2::new(0., 0.), MIN_WIDTH * 2., MIN_HEIGHT * 2.);
+        let large = Block::new_sized(Vector2::new(MIN_WIDTH * 2., 0.), MIN_WIDTH * 6., MIN_HEIGHT * 4.);
+        let resize = resize(ResizeHandle::BottomRight, vec![small, large]);
+        let bounds = resize.resized_bounds(Vector2::new(-1000., -1000.), false);
+        let blocks = resize.apply(&bounds);
+        // The small block limits the scale, the large one stays above its minimum
+        assert_eq!((blocks[0].width, blocks[0].height), (MIN_WIDTH, MIN_HEIGHT));
+        assert_eq!((blocks[1].width, blocks[1].height), (MIN_WIDTH * 3., MIN_HEIGHT * 2.));
+    }
+
+    #[test]
+    fn the_ratio_follows_the_handle_when_locked() {
+        let resize = resize(ResizeHandle::BottomRight, vec![Block::new_sized(Vector2::new(0., 0.), 200., 100.)]);
+        // A corner follows the largest scale
+        let bounds = resize.resized_bounds(Vector2::new(200., 10.), true);
+        assert_eq!((bounds.width(), bounds.height()), (400., 200.));
+        // An edge only follows its own axis
+        let resize = Resize { handle: ResizeHandle::Bottom, ..resize };
+        let bounds = resize.resized_bounds(Vector2::new(200., 100.), true);
+        assert_eq!((bounds.width(), bounds.height()), (400., 200.));
+        let bounds = resize.resized_bounds(Vector2::new(200., 100.), false);
+        assert_eq!((bounds.width(), bounds.height()), (200., 200.));
+    }
+
+    #[test]
+    fn several_blocks_are_scaled_proportionally() {
+        let blocks = vec![
+            Block::new_sized(Vector2::new(0., 0.), 100., 100.),
+            Block::new_sized(Vector2::new(200., 100.), 200., 100.),
+        ];
+        let resize = resize(ResizeHandle::BottomRight, blocks);
+        let blocks = resize.apply(&resize.resized_bounds(Vector2::new(400., 200.), false));
+        let geometry: Vec<_> = blocks.iter().map(|block| (block.pos, block.width, block.height)).collect();
+        assert_eq!(geometry, vec![(Vector2::new(0., 0.), 200., 200.), (Vector2::new(400., 200.), 400., 200.)]);
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use speedy2d::color::Color;
+use speedy2d::dimen::Vector2;
+use speedy2d::Graphics2D;
+use speedy2d::shape::Rectangle;
+
+use crate::camera::Camera;
+use crate::geometry::distance_to_polyline;
+use crate::graph::{Graph, LinkId};
+use crate::link::Link;
+use crate::render_helper::{bezier_point, bezier_tangent, draw_bezier_curve, draw_polyline, FLATNESS_TOLERANCE, flatten_bezier};
+
+const MIN_CONTROL_OFFSET: f32 = 40.;
+const STUB_LENGTH: f32 = 20.; // Straight part leaving and entering the ports of orthogonal links
+const OBSTACLE_MARGIN: f32 = 10.;
+const CORNER_RADIUS: f32 = 10.;
+const CORNER_SUBDIVISION: usize = 6;
+const MIDPOINT_SUBDIVISION: usize = 32;
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    #[default]
+    Bezier,
+    Straight,
+    Orthogonal,
+}
+
+impl LinkStyle {
+    pub fn next(self) -> Self {
+        match self {
+            LinkStyle::Bezier => LinkStyle::Straight,
+            LinkStyle::Straight => LinkStyle::Orthogonal,
+            LinkStyle::Orthogonal => LinkStyle::Bezier,
+        }
+    }
+}
+
+// Shape of a link in world coordinates
+#[derive(Clone, Debug, PartialEq)]
+pub enum Route {
+    Bezier([Vector2<f32>; 4]), // Start, control points and end
+    Polyline(Vec<Vector2<f32>>),
+}
+
+impl Route {
+    // The curve leaves horizontally to the right and enters horizontally from the left
+    pub fn bezier(start: Vector2<f32>, end: Vector2<f32>) -> Self {
+        let offset = ((end.x - start.x).abs() / 2.).max(MIN_CONTROL_OFFSET);
+        Route::Bezier([
+            start,
+            start + Vector2::new(offset, 0.), // control 1
+            end - Vector2::new(offset, 0.), // control 2
+            end,
+        ])
+    }
+
+    // Points along the route, the curves are subdivided in `nb_points` segments and the polylines kept as they are
+    pub fn points(&self, nb_points: usize) -> Vec<Vector2<f32>> {
+        match self {
+            Route::Bezier([start, control1, control2, end]) => {
+                (0..=nb_points).map(|i| bezier_point(*start, *control1, *control2, *end, i as f32 / nb_points as f32)).collect()
+            }
+            Route::Polyline(points) => points.clone(),
+        }
+    }
+
+    pub fn distance_to(&self, point: Vector2<f32>, nb_points: usize) -> f32 {
+        distance_to_polyline(point, &self.points(nb_points))
+    }
+
+    // Points whose convex hull contains the route
+    pub fn hull(&self) -> Vec<Vector2<f32>> {
+        match self {
+            Route::Bezier(curve) => curve.to_vec(),
+            Route::Polyline(points) => points.clone(),
+        }
+    }
+
+    pub fn endpoints(&self) -> (Vector2<f32>, Vector2<f32>) {
+        match self {
+            Route::Bezier([start, .., end]) => (*start, *end),
+            Route::Polyline(points) => (points[0], points[points.len() - 1]),
+        }
+    }
+
+    // Direction of the route leaving the start and reaching the end
+    pub fn tangents(&self) -> (Vector2<f32>, Vector2<f32>) {
+        let (start, end) = match self {
+            Route::Bezier([start, control1, control2, end]) => (
+                bezier_tangent(*start, *control1, *control2, *end, 0.),
+                bezier_tangent(*start, *control1, *control2, *end, 1.),
+            ),
+            Route::Polyline(points) if points.len() >= 2 => (points[1] - points[0], points[points.len() - 1] - points[points.len() - 2]),
+            Route::Polyline(_) => (Vector2::ZERO, Vector2::ZERO),
+        };
+        let direction = |tangent: Vector2<f32>| tangent.normalize().unwrap_or(Vector2::new(1., 0.));
+        (direction(start), direction(end))
+    }
+
+    // Point halfway along the route
+    pub fn midpoint(&self) -> Vector2<f32> {
+        polyline_midpoint(&self.points(MIDPOINT_SUBDIVISION))
+    }
+
+    // Polyline within `tolerance` of the route
+    pub fn flatten(&self, tolerance: f32) -> Vec<Vector2<f32>> {
+        match self {
+            Route::Bezier([start, control1, control2, end]) => flatten_bezier(*start, *control1, *control2, *end, tolerance),
+            Route::Polyline(points) => points.clone(),
+        }
+    }
+
+    pub fn draw(&self, camera: &Camera, width: f32, color: Color, dash: &[f32], graphics: &mut Graphics2D) {
+        match self {
+            Route::Bezier(curve) => {
+                let [start, control1, control2, end] = curve.map(|point| camera.to_screen(point));
+                draw_bezier_curve(start, control1, control2, end, width, color, dash, graphics);
+            }
+            Route::Polyline(points) => {
+                let points: Vec<Vector2<f32>> = points.iter().map(|point| camera.to_screen(*point)).collect();
+                draw_polyline(&points, width, color, dash, graphics);
+            }
+        }
+    }
+}
+
+pub fn polyline_midpoint(points: &[Vector2<f32>]) -> Vector2<f32> {
+    let mut remaining = length(points) / 2.;
+    for segment in points.windows(2) {
+        let segment_length = (segment[1] - segment[0]).magnitude();
+        if segment_length >= remaining && segment_length > 0. {
+            return segment[0] + (segment[1] - segment[0]) * (remaining / segment_length);
+        }
+        remaining -= segment_length;
+    }
+    points[0]
+}
+
+// World tolerance of the flattening at this zoom, it only changes every time the zoom doubles so the
+// cached polylines stay valid while zooming
+pub fn flattening_tolerance(zoom: f32) -> f32 {
+    FLATNESS_TOLERANCE / 2f32.powf(zoom.log2().ceil())
+}
+
+// What the route of a link depends on
+#[derive(Clone, Debug, PartialEq)]
+struct RouteKey {
+    style: LinkStyle,
+    endpoints: (Vector2<f32>, Vector2<f32>),
+    bounds: [Rectangle; 2], // Of the linked blocks
+    obstacles: Vec<Rectangle>,
+    tolerance: f32,
+}
+
+pub struct CachedRoute {
+    key: RouteKey,
+    pub route: Route,
+    pub polyline: Vec<Vector2<f32>>, // Flattened route in world coordinates
+}
+
+// Routes of the links kept from one frame to the next, a route is only recomputed when the blocks it depends on change
+#[derive(Default)]
+pub struct RouteCache {
+    routes: HashMap<LinkId, CachedRoute>,
+}
+
+impl RouteCache {
+    pub fn get(&mut self, graph: &Graph, link: &Link, tolerance: f32) -> Option<&CachedRoute> {
+        let (from, to) = (graph.block(link.from)?, graph.block(link.to)?);
+        let key = RouteKey {
+            style: link.style,
+            endpoints: link.endpoints(from, to),
+            bounds: [from.bounds(), to.bounds()],
+            obstacles: graph.link_obstacles(link),
+            tolerance,
+        };
+        let is_valid = self.routes.get(&link.id).is_some_and(|cached| cached.key == key);
+        if !is_valid {
+            let route = link.route(from, to, &key.obstacles);
+            let polyline = route.flatten(tolerance);
+            self.routes.insert(link.id, CachedRoute { key, route, polyline });
+        }
+        self.routes.get(&link.id)
+    }
+
+    // Forget the removed links
+    pub fn retain(&mut self, graph: &Graph) {
+        self.routes.retain(|id, _| graph.link(*id).is_some());
+    }
+}
+
+// Whether the segment goes through the inside of the rectangle, the segments are horizontal or vertical
+fn crosses(a: Vector2<f32>, b: Vector2<f32>, rect: &Rectangle) -> bool {
+    let (min, max) = (Vector2::new(a.x.min(b.x), a.y.min(b.y)), Vector2::new(a.x.max(b.x), a.y.max(b.y)));
+    min.x < rect.bottom_right().x && max.x > rect.top_left().x && min.y < rect.bottom_right().y && max.y > rect.top_left().y
+}
+
+fn inflate(rect: &Rectangle, margin: f32) -> Rectangle {
+    let margin = Vector2::new(margin, margin);
+    Rectangle::new(*rect.top_left() - margin, *rect.bottom_right() + margin)
+}
+
+fn length(points: &[Vector2<f32>]) -> f32 {
+    points.windows(2).map(|segment| (segment[1] - segment[0]).magnitude()).sum()
+}
+
+// Horizontal and vertical segments from the output port at `start` to the input port at `end`, going around the obstacles.
+// The candidate routes go through the middle or along the sides of the blocks, the shortest one crossing the fewest obstacles is kept.
+pub fn orthogonal_route(start: Vector2<f32>, end: Vector2<f32>, from: &Rectangle, to: &Rectangle, obstacles: &[Rectangle]) -> Vec<Vector2<f32>> {
+    let stub_start = start + Vector2::new(STUB_LENGTH, 0.);
+    let stub_end = end - Vector2::new(STUB_LENGTH, 0.);
+    let obstacles: Vec<Rectangle> = obstacles.iter().map(|rect| inflate(rect, OBSTACLE_MARGIN)).chain([from.clone(), to.clone()]).collect();
+
+    let mut candidates: Vec<Vec<Vector2<f32>>> = vec![];
+    if stub_start.x <= stub_end.x {
+        // One vertical segment between the blocks
+        let mut xs = vec![(stub_start.x + stub_end.x) / 2.];
+        xs.extend(obstacles.iter().flat_map(|rect| [rect.top_left().x, rect.bottom_right().x]).filter(|x| (stub_start.x..=stub_end.x).contains(x)));
+        candidates.extend(xs.into_iter().map(|x| vec![start, Vector2::new(x, start.y), Vector2::new(x, end.y), end]));
+    }
+    // Back to the left through a horizontal segment above, between or below the blocks
+    let mut ys = vec![(start.y + end.y) / 2., from.top_left().y.min(to.top_left().y) - OBSTACLE_MARGIN, from.bottom_right().y.max(to.bottom_right().y) + OBSTACLE_MARGIN];
+    ys.extend(obstacles.iter().flat_map(|rect| [rect.top_left().y, rect.bottom_right().y]));
+    candidates.extend(ys.into_iter().map(|y| vec![start, stub_start, Vector2::new(stub_start.x, y), Vector2::new(stub_end.x, y), stub_end, end]));
+
+    let nb_crossings = |points: &Vec<Vector2<f32>>| {
+        points.windows(2).map(|segment| obstacles.iter().filter(|rect| crosses(segment[0], segment[1], rect)).count()).sum::<usize>()
+    };
+    let best = candidates.into_iter()
+        .map(|points| (nb_crossings(&points), length(&points), points))
+        .min_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+        .map(|(_, _, points)| points)
+        .unwrap_or_else(|| vec![start, end]);
+    round_corners(&simplify(best))
+}
+
+// Remove the empty segments and the points in the middle of a straight line
+fn simplify(points: Vec<Vector2<f32>>) -> Vec<Vector2<f32>> {
+    let mut simplified: Vec<Vector2<f32>> = vec![];
+    for point in points {
+        if simplified.last().is_some_and(|last| (*last - point).magnitude() < 0.01) { continue; }
+        if let [.., a, b] = simplified[..] {
+            let is_aligned = (a.x == b.x && b.x == point.x) || (a.y == b.y && b.y == point.y);
+            if is_aligned { simplified.pop(); }
+        }
+        simplified.push(point);
+    }
+    simplified
+}
+
+// Replace each corner by a quadratic curve
+fn round_corners(points: &[Vector2<f32>]) -> Vec<Vector2<f32>> {
+    if points.len() < 3 { return points.to_vec(); }
+    let mut rounded = vec![points[0]];
+    for window in points.windows(3) {
+        let [previous, corner, next] = [window[0], window[1], window[2]];
+        let (incoming, outgoing) = (corner - previous, next - corner);
+        let radius = CORNER_RADIUS.min(incoming.magnitude() / 2.).min(outgoing.magnitude() / 2.);
+        let (Some(incoming), Some(outgoing)) = (incoming.normalize(), outgoing.normalize()) else { continue; };
+        let (a, b) = (corner - incoming * radius, corner + outgoing * radius);
+        for i in 0..=CORNER_SUBDIVISION {
+            let t = i as f32 / CORNER_SUBDIVISION as f32;
+            rounded.push(a * (1. - t) * (1. - t) + corner * (2. * t * (1. - t)) + b * (t * t));
+        }
+    }
+    rounded.push(points[points.len() - 1]);
+    rounded
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::dimen::Vector2;
+
+    use crate::block::Block;
+    use crate::graph::Graph;
+    use crate::routing::{crosses, flattening_tolerance, orthogonal_route, Route, RouteCache};
+    use crate::test_helpers::rect;
+
+    #[test]
+    fn orthogonal_routes_avoid_the_blocks() {
+        let (from, to) = (rect(0., 0., 100., 40.), rect(400., 100., 100., 40.));
+        let obstacles = [rect(200., -50., 100., 300.)];
+        let route = orthogonal_route(Vector2::new(100., 20.), Vector2::new(400., 120.), &from, &to, &obstacles);
+        assert_eq!(route.first(), Some(&Vector2::new(100., 20.)));
+        assert_eq!(route.last(), Some(&Vector2::new(400., 120.)));
+        assert!(route.windows(2).all(|segment| !crosses(segment[0], segment[1], &obstacles[0])));
+    }
+
+    #[test]
+    fn backward_routes_go_around_the_endpoints() {
+        let (from, to) = (rect(300., 0., 100., 40.), rect(0., 10., 100., 40.));
+        let route = orthogonal_route(Vector2::new(400., 20.), Vector2::new(0., 30.), &from, &to, &[]);
+        // Only the stubs touch the blocks
+        assert!(route[1..route.len() - 1].windows(2).all(|segment| !crosses(segment[0], segment[1], &from) && !crosses(segment[0], segment[1], &to)));
+    }
+
+    #[test]
+    fn tangents_follow_the_curve() {
+        let route = Route::Bezier([Vector2::new(0., 0.), Vector2::new(0., 100.), Vector2::new(100., 200.), Vector2::new(200., 200.)]);
+        assert_eq!(route.tangents(), (Vector2::new(0., 1.), Vector2::new(1., 0.)));
+        let route = Route::Polyline(vec![Vector2::new(0., 0.), Vector2::new(100., 0.), Vector2::new(100., 50.)]);
+        assert_eq!(route.tangents(), (Vector2::new(1., 0.), Vector2::new(0., 1.)));
+        assert_eq!(route.midpoint(), Vector2::new(75., 0.));
+    }
+
+    #[test]
+    fn flattening_adapts_to_the_tolerance() {
+        let straight = Route::Bezier([Vector2::new(0., 0.), Vector2::new(10., 0.), Vector2::new(20., 0.), Vector2::new(30., 0.)]);
+        assert_eq!(straight.flatten(0.25).len(), 2);
+        let curve = Route::bezier(Vector2::new(0., 0.), Vector2::new(300., 200.));
+        let (coarse, fine) = (curve.flatten(1.).len(), curve.flatten(0.01).len());
+        assert!(coarse > 2 && fine > coarse);
+        assert_eq!(flattening_tolerance(1.), 0.25);
+        assert_eq!(flattening_tolerance(1.5), flattening_tolerance(2.));
+    }
+
+    #[test]
+    fn cached_routes_follow_the_blocks() {
+        let mut graph = Graph::new();
+        let a = graph.add_block(Block::new(Vector2::new(0., 0.)));
+        let b = graph.add_block(Block::new(Vector2::new(300., 100.)));
+        let id = graph.add_link(a, 0, b, 0).unwrap();
+        let mut cache = RouteCache::default();
+        let before = cache.get(&graph, graph.link(id).unwrap(), 0.25).unwrap().polyline.clone();
+        assert_eq!(cache.get(&graph, graph.link(id).unwrap(), 0.25).unwrap().polyline, before);
+        graph.block_mut(b).unwrap().pos.y += 50.;
+        assert_ne!(cache.get(&graph, graph.link(id).unwrap(), 0.25).unwrap().polyline, before);
+        graph.remove_link(id);
+        cache.retain(&graph);
+        assert!(cache.routes.is_empty());
+    }
+}
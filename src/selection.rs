@@ -0,0 +1,93 @@
+use speedy2d::color::Color;
+use speedy2d::dimen::Vector2;
+use speedy2d::Graphics2D;
+use speedy2d::shape::Rectangle;
+
+use crate::camera::Camera;
+use crate::geometry::segment_intersects_rect;
+use crate::graph::{BlockId, LinkId};
+use crate::render_helper::{draw_rect_border, draw_rectangle};
+
+const FILL_COLOR: Color = Color::from_rgba(0.2, 0.4, 1., 0.1);
+const BORDER_COLOR: Color = Color::from_rgba(0.2, 0.4, 1., 0.8);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SelectionMode {
+    Contained, // Dragging to the right selects the elements fully inside the rectangle
+    Intersecting, // Dragging to the left selects the elements touching the rectangle
+}
+
+// Rectangle selection drawn by dragging on the empty canvas
+pub struct RubberBand {
+    pub origin: Vector2<f32>, // World positions
+    pub corner: Vector2<f32>,
+    pub base_blocks: Vec<BlockId>, // Selection before the drag, kept for additive selections
+    pub base_links: Vec<LinkId>,
+}
+
+impl RubberBand {
+    pub fn new(origin: Vector2<f32>, base_blocks: Vec<BlockId>, base_links: Vec<LinkId>) -> Self {
+        Self {
+            origin,
+            corner: origin,
+            base_blocks,
+            base_links,
+        }
+    }
+
+    pub fn rect(&self) -> Rectangle {
+        Rectangle::new(
+            Vector2::new(self.origin.x.min(self.corner.x), self.origin.y.min(self.corner.y)),
+            Vector2::new(self.origin.x.max(self.corner.x), self.origin.y.max(self.corner.y)),
+        )
+    }
+
+    pub fn mode(&self) -> SelectionMode {
+        if self.corner.x >= self.origin.x { SelectionMode::Contained } else { SelectionMode::Intersecting }
+    }
+
+    pub fn selects_rect(&self, rect: &Rectangle) -> bool {
+        let band = self.rect();
+        match self.mode() {
+            SelectionMode::Contained => band.contains(*rect.top_left()) && band.contains(*rect.bottom_right()),
+            SelectionMode::Intersecting => band.intersect(rect).is_some(),
+        }
+    }
+
+    // The band is convex, the polyline is inside when all its points are
+    pub fn selects_polyline(&self, points: &[Vector2<f32>]) -> bool {
+        let band = self.rect();
+        match self.mode() {
+            SelectionMode::Contained => points.iter().all(|point| band.contains(*point)),
+            SelectionMode::Intersecting if points.len() == 1 => band.contains(points[0]),
+            SelectionMode::Intersecting => points.windows(2).any(|segment| segment_intersects_rect(segment[0], segment[1], &band)),
+        }
+    }
+
+    pub fn render(&self, camera: &Camera, graphics: &mut Graphics2D) {
+        let rect = camera.rect_to_screen(&self.rect());
+        let (x, y, width, height) = (rect.top_left().x, rect.top_left().y, rect.width(), rect.height());
+        draw_rectangle(x, y, width, height, FILL_COLOR, graphics);
+        draw_rect_border(*rect.top_left(), width, height, 1., BORDER_COLOR, graphics);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::dimen::Vector2;
+
+    use crate::selection::RubberBand;
+
+    #[test]
+    fn links_crossing_the_band_are_selected() {
+        let mut rubber_band = RubberBand::new(Vector2::new(100., 100.), vec![], vec![]);
+        rubber_band.corner = Vector2::new(0., 0.); // Dragged to the left
+        let crossing = [Vector2::new(-50., 50.), Vector2::new(150., 60.)];
+        let missing = [Vector2::new(-50., 150.), Vector2::new(50., 250.), Vector2::new(250., 50.)];
+        assert!(rubber_band.selects_polyline(&crossing));
+        assert!(!rubber_band.selects_polyline(&missing));
+        rubber_band.corner = Vector2::new(200., 200.); // Dragged to the right
+        assert!(!rubber_band.selects_polyline(&crossing));
+        assert!(rubber_band.selects_polyline(&[Vector2::new(110., 110.), Vector2::new(190., 150.)]));
+    }
+}
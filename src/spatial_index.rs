@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use speedy2d::dimen::Vector2;
+use speedy2d::shape::Rectangle;
+
+use crate::graph::BlockId;
+
+const CELL_SIZE: f32 = 256.; // World size of a cell, a few blocks wide
+const MAX_CELLS: usize = 64; // Larger rectangles are kept out of the cells
+
+type Cell = (i32, i32);
+
+fn cell_of(point: Vector2<f32>) -> Cell {
+    ((point.x / CELL_SIZE).floor() as i32, (point.y / CELL_SIZE).floor() as i32)
+}
+
+// Touching rectangles overlap
+fn overlaps(a: &Rectangle, b: &Rectangle) -> bool {
+    a.top_left().x <= b.bottom_right().x && b.top_left().x <= a.bottom_right().x
+        && a.top_left().y <= b.bottom_right().y && b.top_left().y <= a.bottom_right().y
+}
+
+// Uniform grid of the bounds of the blocks (or of the links), each one is registered in every cell it overlaps
+#[derive(Clone, Default)]
+pub struct SpatialIndex {
+    cells: HashMap<Cell, Vec<BlockId>>,
+    oversized: Vec<BlockId>, // Over more than MAX_CELLS cells, checked by every query
+    bounds: HashMap<BlockId, Rectangle>, // As indexed
+}
+
+impl SpatialIndex {
+    fn cells(rect: &Rectangle) -> impl Iterator<Item = Cell> {
+        let (min, max) = (cell_of(*rect.top_left()), cell_of(*rect.bottom_right()));
+        (min.0..=max.0).flat_map(move |x| (min.1..=max.1).map(move |y| (x, y)))
+    }
+
+    fn nb_cells(rect: &Rectangle) -> usize {
+        let (min, max) = (cell_of(*rect.top_left()), cell_of(*rect.bottom_right()));
+        let (columns, rows) = (max.0 as i64 - min.0 as i64 + 1, max.1 as i64 - min.1 as i64 + 1);
+        usize::try_from(columns.saturating_mul(rows)).unwrap_or(0)
+    }
+
+    pub fn bounds(&self, id: BlockId) -> Option<Rectangle> {
+        self.bounds.get(&id).cloned()
+    }
+
+    pub fn update(&mut self, id: BlockId, bounds: Rectangle) {
+        if self.bounds.get(&id) == Some(&bounds) { return; }
+        self.remove(id);
+        if Self::nb_cells(&bounds) > MAX_CELLS {
+            self.oversized.push(id);
+        } else {
+            for cell in Self::cells(&bounds) { self.cells.entry(cell).or_default().push(id); }
+        }
+        self.bounds.insert(id, bounds);
+    }
+
+    pub fn remove(&mut self, id: BlockId) {
+        let Some(bounds) = self.bounds.remove(&id) else { return; };
+        if Self::nb_cells(&bounds) > MAX_CELLS {
+            self.oversized.retain(|other| *other != id);
+            return;
+        }
+        for cell in Self::cells(&bounds) {
+            let Some(ids) = self.cells.get_mut(&cell) else { continue; };
+            ids.retain(|other| *other != id);
+            if ids.is_empty() { self.cells.remove(&cell); }
+        }
+    }
+
+    // Blocks overlapping the rectangle, sorted by id
+    pub fn query(&self, rect: &Rectangle) -> Vec<BlockId> {
+        let mut ids: Vec<BlockId> = if Self::nb_cells(rect) > self.bounds.len() {
+            // Faster to check every block than every cell when zoomed out
+            self.bounds.keys().copied().collect()
+        } else {
+            let cells = Self::cells(rect).filter_map(|cell| self.cells.get(&cell)).flatten();
+            cells.chain(&self.oversized).copied().collect()
+        };
+        ids.sort_unstable();
+        ids.dedup();
+        ids.retain(|id| overlaps(&self.bounds[id], rect));
+        ids
+    }
+
+    // Blocks whose bounds contain the point, sorted by id
+    pub fn at(&self, point: Vector2<f32>) -> Vec<BlockId> {
+        let ids = self.cells.get(&cell_of(point)).into_iter().flatten().chain(&self.oversized);
+        let mut ids: Vec<BlockId> = ids.copied().filter(|id| self.bounds[id].contains(point)).collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::dimen::Vector2;
+
+    use crate::block::Block;
+    use crate::graph::Graph;
+    use crate::routing::LinkStyle;
+    use crate::spatial_index::SpatialIndex;
+    use crate::test_helpers::rect;
+
+    #[test]
+    fn queries_follow_the_updates() {
+        let mut index = SpatialIndex::default();
+        index.update(0, rect(0., 0., 100., 50.));
+        index.update(1, rect(200., 300., 400., 50.)); // Over several cells
+        index.update(2, rect(-500., -500., 10., 10.));
+        assert_eq!(index.query(&rect(50., 0., 300., 400.)), vec![0, 1]);
+        assert_eq!(index.at(Vector2::new(550., 320.)), vec![1]);
+        index.update(1, rect(1000., 1000., 10., 10.));
+        assert_eq!(index.query(&rect(50., 0., 300., 400.)), vec![0]);
+        assert!(index.at(Vector2::new(550., 320.)).is_empty());
+        index.remove(0);
+        assert_eq!(index.query(&rect(-1000., -1000., 3000., 3000.)), vec![1, 2]);
+    }
+
+    #[test]
+    fn huge_rectangles_are_found_without_filling_the_cells() {
+        let mut index = SpatialIndex::default();
+        index.update(0, rect(-1e7, -1e7, 2e7, 2e7));
+        index.update(1, rect(0., 0., 10., 10.));
+        assert!(index.cells.len() <= 1);
+        assert_eq!(index.at(Vector2::new(5., 5.)), vec![0, 1]);
+        assert_eq!(index.query(&rect(5e6, 5e6, 10., 10.)), vec![0]);
+        index.update(0, rect(100., 100., 10., 10.));
+        assert!(index.oversized.is_empty());
+        assert_eq!(index.query(&rect(5e6, 5e6, 10., 10.)), Vec::<u32>::new());
+        index.update(2, rect(f32::MIN, f32::MIN, f32::MAX, f32::MAX));
+        assert_eq!(index.at(Vector2::new(-5e30, -5e30)), vec![2]);
+    }
+
+    #[test]
+    fn graph_queries_see_the_moved_blocks() {
+        let mut graph = Graph::new();
+        let id = graph.add_block(Block::new_sized(Vector2::new(0., 0.), 100., 50.));
+        assert_eq!(graph.block_at(Vector2::new(50., 25.)), Some(id));
+        graph.block_mut(id).unwrap().pos = Vector2::new(1000., 0.);
+        assert_eq!(graph.block_at(Vector2::new(50., 25.)), None);
+        assert_eq!(graph.blocks_in(&rect(900., -10., 200., 20.)), vec![id]);
+        graph.remove_blocks(&[id]);
+        assert!(graph.blocks_in(&rect(900., -10., 200., 20.)).is_empty());
+    }
+
+    #[test]
+    fn link_queries_see_the_moved_blocks() {
+        let mut graph = Graph::new();
+        let a = graph.add_block(Block::new_sized(Vector2::new(0., 0.), 100., 50.));
+        let b = graph.add_block(Block::new_sized(Vector2::new(300., 0.), 100., 50.));
+        let link = graph.add_link(a, 0, b, 0).unwrap();
+        graph.link_mut(link).unwrap().style = LinkStyle::Orthogonal;
+        assert_eq!(graph.links_in(&rect(150., 0., 10., 10.)), vec![link]);
+        assert!(graph.links_in(&rect(150., 2000., 10., 10.)).is_empty());
+        graph.block_mut(b).unwrap().pos = Vector2::new(300., 2000.);
+        assert_eq!(graph.links_in(&rect(150., 2000., 10., 10.)), vec![link]);
+        // A block entering the routing area becomes an obstacle and widens the bounds
+        let obstacle = graph.add_block(Block::new_sized(Vector2::new(-1000., 1000.), 1200., 50.));
+        assert_eq!(graph.link_obstacles(graph.link(link).unwrap()).len(), 1);
+        assert_eq!(graph.links_in(&rect(-900., 1000., 10., 10.)), vec![link]);
+        graph.remove_blocks(&[obstacle]);
+        assert!(graph.links_in(&rect(-900., 1000., 10., 10.)).is_empty());
+        graph.remove_blocks(&[a]);
+        assert!(graph.links_in(&rect(-1000., -1000., 3000., 4000.)).is_empty());
+    }
+}
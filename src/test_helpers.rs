@@ -0,0 +1,29 @@
+use speedy2d::dimen::Vector2;
+use speedy2d::shape::Rectangle;
+
+use crate::block::Block;
+use crate::graph::{BlockId, Graph};
+
+// Fixtures shared by the tests of the modules
+
+pub fn rect(x: f32, y: f32, width: f32, height: f32) -> Rectangle {
+    Rectangle::new(Vector2::new(x, y), Vector2::new(x + width, y + height))
+}
+
+// Not added to a graph
+pub fn sized_block(id: BlockId, x: f32, y: f32, width: f32, height: f32) -> Block {
+    let mut block = Block::new_sized(Vector2::new(x, y), width, height);
+    block.id = id;
+    block
+}
+
+pub fn add_block(graph: &mut Graph, x: f32) -> BlockId {
+    graph.add_block(Block::new(Vector2::new(x, 0.)))
+}
+
+// The blocks are on a row, 100 apart
+pub fn graph_with_blocks(nb_blocks: usize) -> (Graph, Vec<BlockId>) {
+    let mut graph = Graph::new();
+    let ids = (0..nb_blocks).map(|i| add_block(&mut graph, i as f32 * 100.)).collect();
+    (graph, ids)
+}
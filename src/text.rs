@@ -0,0 +1,133 @@
+use std::rc::Rc;
+
+use speedy2d::font::{Font, FormattedTextBlock, TextLayout, TextOptions};
+
+pub const TITLE_FONT_SIZE: f32 = 16.;
+pub const BODY_FONT_SIZE: f32 = 13.;
+pub const LINE_HEIGHT: f32 = 1.3; // Relative to the font size
+
+thread_local! {
+    static FONT: Font = Font::new(include_bytes!("../resources/fonts/NotoSans-Regular.ttf")).unwrap();
+}
+
+// A visual line of a label, after word wrapping
+#[derive(Clone)]
+pub struct TextLine {
+    pub text: String,
+    pub start: usize, // Index of the first char in the label
+    pub len: usize,
+    pub ends_paragraph: bool, // The line is followed by a '\n' or the end of the text
+    pub font_size: f32,
+    pub layout: Rc<FormattedTextBlock>,
+}
+
+impl TextLine {
+    #[inline]
+    pub fn height(&self) -> f32 {
+        self.font_size * LINE_HEIGHT
+    }
+}
+
+pub fn layout_text(text: &str, font_size: f32) -> Rc<FormattedTextBlock> {
+    FONT.with(|font| font.layout_text(text, font_size, TextOptions::new()))
+}
+
+pub fn text_width(text: &str, font_size: f32) -> f32 {
+    if text.is_empty() { return 0.; }
+    layout_text(text, font_size).width()
+}
+
+// The first line of a label is its title, the following ones are the body
+#[inline]
+pub fn label_font_size(line_index: usize) -> f32 {
+    if line_index == 0 { TITLE_FONT_SIZE } else { BODY_FONT_SIZE }
+}
+
+// Extent of the glyphs of a paragraph laid out once, to measure any of its slices
+struct GlyphExtents {
+    starts: Vec<f32>, // Left of the first glyph from each char, the chars without glyph (e.g. spaces) take the next one
+    ends: Vec<f32>, // Right of the last glyph up to each char
+}
+
+impl GlyphExtents {
+    fn new(chars: &[char], font_size: f32) -> Self {
+        let mut starts = vec![f32::NAN; chars.len()];
+        let mut ends = vec![f32::NAN; chars.len()];
+        let layout = FONT.with(|font| font.layout_text_from_unindexed_codepoints(chars, font_size, TextOptions::new()));
+        for glyph in layout.iter_lines().flat_map(|line| line.iter_glyphs()) {
+            let i = glyph.user_index() as usize;
+            starts[i] = glyph.position_x();
+            ends[i] = glyph.position_x() + glyph.advance_width();
+        }
+        for i in (1..chars.len()).rev() {
+            if starts[i - 1].is_nan() { starts[i - 1] = starts[i]; }
+        }
+        for i in 1..chars.len() {
+            if ends[i].is_nan() { ends[i] = ends[i - 1]; }
+        }
+        Self { starts, ends }
+    }
+
+    // Width of the chars start..end, without their leading and trailing spaces
+    fn width(&self, start: usize, end: usize) -> f32 {
+        if start >= end { return 0.; }
+        (self.ends[end - 1] - self.starts[start]).max(0.) // NaN when there is no glyph
+    }
+}
+
+// Split the label in visual lines no wider than `max_width`, breaking after spaces when possible
+pub fn wrap_label(text: &str, max_width: f32) -> Vec<TextLine> {
+    let mut lines = vec![];
+    let mut paragraph_start = 0;
+    for (i, paragraph) in text.split('\n').enumerate() {
+        let font_size = label_font_size(i);
+        let chars: Vec<char> = paragraph.chars().collect();
+        let extents = GlyphExtents::new(&chars, font_size);
+        let width = |start: usize, end: usize| extents.width(start, end);
+        let mut start = 0;
+        loop {
+            let mut end = start;
+            let mut last_break = None;
+            while end < chars.len() {
+                if end > start && width(start, end + 1) > max_width { break; }
+                end += 1;
+                if chars[end - 1] == ' ' { last_break = Some(end); }
+            }
+            if end < chars.len() {
+                if let Some(last_break) = last_break { end = last_break; }
+            }
+            let text: String = chars[start..end].iter().collect();
+            lines.push(TextLine {
+                layout: layout_text(&text, font_size),
+                text,
+                start: paragraph_start + start,
+                len: end - start,
+                ends_paragraph: end == chars.len(),
+                font_size,
+            });
+            if end == chars.len() { break; }
+            start = end;
+        }
+        paragraph_start += chars.len() + 1;
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::text::{BODY_FONT_SIZE, text_width, wrap_label};
+
+    #[test]
+    fn wrapped_lines_are_filled_up_to_the_width() {
+        let text = "Title\nA body long enough to be wrapped on several lines of text";
+        let lines = wrap_label(text, 120.);
+        assert!(lines.len() > 3);
+        assert!(lines.iter().all(|line| line.layout.width() <= 120.));
+        let line_text = |index: usize| text.chars().skip(lines[index].start).take(lines[index].len).collect::<String>();
+        let body: String = (1..lines.len()).map(line_text).collect();
+        assert_eq!(body, "A body long enough to be wrapped on several lines of text");
+        // The next word doesn't fit
+        let next_word = line_text(2).split(' ').next().unwrap().to_string();
+        assert!(text_width(&format!("{}{}", line_text(1), next_word), BODY_FONT_SIZE) > 120.);
+    }
+}
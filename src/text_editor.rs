@@ -0,0 +1,287 @@
+use speedy2d::color::Color;
+use speedy2d::dimen::Vector2;
+use speedy2d::Graphics2D;
+
+use crate::block::{Block, TEXT_PADDING};
+use crate::camera::Camera;
+use crate::graph::{BlockId, LinkId};
+use crate::link::Link;
+use crate::port::PortRef;
+use crate::render_helper::draw_rectangle;
+use crate::text::{layout_text, LINE_HEIGHT, text_width, wrap_label};
+
+const SELECTION_COLOR: Color = Color::from_rgba(0.3, 0.5, 1., 0.3);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EditTarget {
+    Block(BlockId),
+    Link(LinkId), // Single line label of a link
+    Port(BlockId, PortRef), // "name: type" of a port
+}
+
+// In place editor of a label, the first line of a block label is the title and the following ones the body
+pub struct TextEditor {
+    pub target: EditTarget,
+    text: Vec<char>,
+    cursor: usize,
+    selection_start: Option<usize>,
+}
+
+impl TextEditor {
+    pub fn new(block: &Block) -> Self {
+        Self::with_text(EditTarget::Block(block.id), &block.label())
+    }
+
+    pub fn for_link(link: &Link) -> Self {
+        Self::with_text(EditTarget::Link(link.id), &link.label)
+    }
+
+    pub fn for_port(block: &Block, port: PortRef) -> Self {
+        let label = block.ports(port.side).get(port.index).map(|port| port.label()).unwrap_or_default();
+        Self::with_text(EditTarget::Port(block.id, port), &label)
+    }
+
+    fn with_text(target: EditTarget, text: &str) -> Self {
+        let text: Vec<char> = text.chars().collect();
+        Self {
+            target,
+            cursor: text.len(),
+            text,
+            selection_start: None,
+        }
+    }
+
+    pub fn is_single_line(&self) -> bool {
+        matches!(self.target, EditTarget::Link(_) | EditTarget::Port(..))
+    }
+
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    // Returns the (title, body) of the edited label
+    pub fn label(&self) -> (String, String) {
+        let text = self.text();
+        match text.split_once('\n') {
+            Some((title, body)) => (title.to_string(), body.to_string()),
+            None => (text, String::new()),
+        }
+    }
+
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        let start = self.selection_start?;
+        if start == self.cursor { return None; }
+        Some((start.min(self.cursor), start.max(self.cursor)))
+    }
+
+    pub fn insert(&mut self, string: &str) {
+        self.delete_selection();
+        for c in string.chars() {
+            self.text.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if self.delete_selection() || self.cursor == 0 { return; }
+        self.cursor -= 1;
+        self.text.remove(self.cursor);
+    }
+
+    pub fn delete(&mut self) {
+        if self.delete_selection() || self.cursor == self.text.len() { return; }
+        self.text.remove(self.cursor);
+    }
+
+    pub fn select_all(&mut self) {
+        self.selection_start = Some(0);
+        self.cursor = self.text.len();
+    }
+
+    pub fn move_left(&mut self, select: bool) {
+        let target = match self.selection() {
+            Some((start, _)) if !select => start,
+            _ => self.cursor.saturating_sub(1),
+        };
+        self.move_cursor(target, select);
+    }
+
+    pub fn move_right(&mut self, select: bool) {
+        let target = match self.selection() {
+            Some((_, end)) if !select => end,
+            _ => (self.cursor + 1).min(self.text.len()),
+        };
+        self.move_cursor(target, select);
+    }
+
+    pub fn move_up(&mut self, select: bool) {
+        let (line, column) = self.position_of(self.cursor);
+        let target = if line == 0 { 0 } else { self.index_of(line - 1, column) };
+        self.move_cursor(target, select);
+    }
+
+    pub fn move_down(&mut self, select: bool) {
+        let (line, column) = self.position_of(self.cursor);
+        let target = if line + 1 >= self.lines().len() { self.text.len() } else { self.index_of(line + 1, column) };
+        self.move_cursor(target, select);
+    }
+
+    pub fn move_to_line_start(&mut self, select: bool) {
+        let (line, _) = self.position_of(self.cursor);
+        self.move_cursor(self.index_of(line, 0), select);
+    }
+
+    pub fn move_to_line_end(&mut self, select: bool) {
+        let (line, _) = self.position_of(self.cursor);
+        self.move_cursor(self.index_of(line, usize::MAX), select);
+    }
+
+    fn move_cursor(&mut self, target: usize, select: bool) {
+        if select {
+            if self.selection_start.is_none() { self.selection_start = Some(self.cursor); }
+        } else {
+            self.selection_start = None;
+        }
+        self.cursor = target;
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        self.selection_start = None;
+        let Some((start, end)) = selection else { return false; };
+        self.text.drain(start..end);
+        self.cursor = start;
+        true
+    }
+
+    fn lines(&self) -> Vec<&[char]> {
+        self.text.split(|c| *c == '\n').collect()
+    }
+
+    // (line, column) of a char index
+    fn position_of(&self, index: usize) -> (usize, usize) {
+        let mut line_start = 0;
+        for (i, line) in self.lines().iter().enumerate() {
+            if index <= line_start + line.len() { return (i, index - line_start); }
+            line_start += line.len() + 1;
+        }
+        (0, 0)
+    }
+
+    // Char index of a (line, column), the column is clamped to the line length
+    fn index_of(&self, line: usize, column: usize) -> usize {
+        let lines = self.lines();
+        let line_start: usize = lines.iter().take(line).map(|line| line.len() + 1).sum();
+        line_start + column.min(lines[line].len())
+    }
+
+    // Single line centered on `center`, in screen coordinates
+    pub fn render_line(&self, center: Vector2<f32>, font_size: f32, graphics: &mut Graphics2D) {
+        let text = self.text();
+        let x_of = |index: usize| text_width(&self.text[..index].iter().collect::<String>(), font_size);
+        let (width, height) = (x_of(self.text.len()), font_size * LINE_HEIGHT);
+        let origin = center - Vector2::new(width, height) / 2.;
+        draw_rectangle(origin.x - 3., origin.y, width + 6., height, Color::WHITE, graphics);
+        if let Some((start, end)) = self.selection() {
+            draw_rectangle(origin.x + x_of(start), origin.y, x_of(end) - x_of(start), height, SELECTION_COLOR, graphics);
+        }
+        if !text.is_empty() { graphics.draw_text(origin, Color::BLACK, &layout_text(&text, font_size)); }
+        let x = origin.x + x_of(self.cursor);
+        graphics.draw_line(Vector2::new(x, origin.y), Vector2::new(x, origin.y + height), 1., Color::BLACK);
+    }
+
+    pub fn render(&self, block: &Block, camera: &Camera, graphics: &mut Graphics2D) {
+        let text = self.text();
+        let chars: Vec<char> = self.text.clone();
+        let origin = camera.to_screen(block.pos + Vector2::new(TEXT_PADDING, TEXT_PADDING));
+        let selection = self.selection();
+        let mut y = origin.y;
+        for line in wrap_label(&text, block.wrap_width()) {
+            let end = line.start + line.len;
+            let (font_size, line_height) = (line.font_size * camera.zoom, line.height() * camera.zoom);
+            let x_of = |index: usize| origin.x + text_width(&chars[line.start..index].iter().collect::<String>(), font_size);
+            if let Some((start, selection_end)) = selection {
+                let (from, to) = (start.clamp(line.start, end), selection_end.clamp(line.start, end));
+                let selects_line_break = line.ends_paragraph && start <= end && selection_end > end;
+                if from < to || selects_line_break {
+                    let x = x_of(from);
+                    let width = (x_of(to) - x).max(3.);
+                    draw_rectangle(x, y, width, line_height, SELECTION_COLOR, graphics);
+                }
+            }
+            let layout = if camera.zoom == 1. { line.layout.clone() } else { layout_text(&line.text, font_size) };
+            graphics.draw_text(Vector2::new(origin.x, y), Color::BLACK, &layout);
+            let has_cursor = (line.start..end).contains(&self.cursor) || (self.cursor == end && line.ends_paragraph);
+            if has_cursor {
+                let x = x_of(self.cursor);
+                graphics.draw_line(Vector2::new(x, y), Vector2::new(x, y + line_height), 1., Color::BLACK);
+            }
+            y += line_height;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::dimen::Vector2;
+
+    use crate::block::Block;
+    use crate::text_editor::TextEditor;
+
+    fn editor(text: &str) -> TextEditor {
+        let mut block = Block::new(Vector2::ZERO);
+        (block.title, block.body) = match text.split_once('\n') {
+            Some((title, body)) => (title.to_string(), body.to_string()),
+            None => (text.to_string(), String::new()),
+        };
+        TextEditor::new(&block)
+    }
+
+    fn text(editor: &TextEditor) -> String {
+        editor.text.iter().collect()
+    }
+
+    #[test]
+    fn up_and_down_keep_the_column_within_the_lines() {
+        let mut editor = editor("Title\nab\nlonger line");
+        editor.move_up(false);
+        assert_eq!(editor.cursor, 8); // Clamped to the end of "ab"
+        editor.move_up(false);
+        assert_eq!(editor.cursor, 2);
+        editor.move_down(false);
+        assert_eq!(editor.cursor, 8);
+        editor.move_to_line_start(false);
+        editor.move_right(false);
+        editor.move_down(false);
+        assert_eq!(editor.cursor, 10);
+        editor.move_down(false);
+        assert_eq!(editor.cursor, editor.text.len());
+        editor.move_up(true);
+        assert_eq!(editor.selection(), Some((8, 20)));
+    }
+
+    #[test]
+    fn typing_replaces_the_selection() {
+        let mut editor = editor("Hello world");
+        editor.move_to_line_start(false);
+        for _ in 0..5 { editor.move_right(true); }
+        editor.insert("Goodbye");
+        assert_eq!(text(&editor), "Goodbye world");
+        assert_eq!(editor.selection(), None);
+        editor.select_all();
+        editor.backspace();
+        assert_eq!(text(&editor), "");
+    }
+
+    #[test]
+    fn backspace_at_a_line_start_joins_the_lines() {
+        let mut editor = editor("Title\nBody");
+        editor.move_to_line_start(false);
+        editor.backspace();
+        assert_eq!(editor.label(), (String::from("TitleBody"), String::new()));
+        assert_eq!(editor.cursor, 5);
+        editor.move_to_line_start(false);
+        editor.backspace();
+        assert_eq!(text(&editor), "TitleBody");
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph::BlockId;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Restack {
+    Forward, // Above the next block
+    Backward, // Below the previous block
+    ToFront,
+    ToBack,
+}
+
+// Whether the links are drawn over or under the blocks
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkLayer {
+    #[default]
+    Below,
+    Above,
+}
+
+impl LinkLayer {
+    pub fn toggle(self) -> Self {
+        match self {
+            LinkLayer::Below => LinkLayer::Above,
+            LinkLayer::Above => LinkLayer::Below,
+        }
+    }
+}
+
+// New order of the blocks, from the bottom to the top, the selected blocks keep their relative order
+pub fn restack(order: &[BlockId], selected: &HashSet<BlockId>, restack: Restack) -> Vec<BlockId> {
+    let (moved, others): (Vec<BlockId>, Vec<BlockId>) = order.iter().partition(|id| selected.contains(id));
+    match restack {
+        Restack::ToFront => others.into_iter().chain(moved).collect(),
+        Restack::ToBack => moved.into_iter().chain(others).collect(),
+        Restack::Forward => {
+            let mut order = order.to_vec();
+            // From the top so that a selected block doesn't jump over the other selected blocks
+            for i in (0..order.len().saturating_sub(1)).rev() {
+                if selected.contains(&order[i]) && !selected.contains(&order[i + 1]) { order.swap(i, i + 1); }
+            }
+            order
+        }
+        Restack::Backward => {
+            let mut order = order.to_vec();
+            for i in 1..order.len() {
+                if selected.contains(&order[i]) && !selected.contains(&order[i - 1]) { order.swap(i, i - 1); }
+            }
+            order
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use speedy2d::dimen::Vector2;
+    use speedy2d::window::MouseButton;
+
+    use crate::block::Block;
+    use crate::context::Context;
+    use crate::graph::Graph;
+    use crate::port::{Port, PortRef, PortSide};
+    use crate::z_order::{restack, Restack};
+
+    #[test]
+    fn selected_blocks_move_one_step_or_to_the_end() {
+        let order = [0, 1, 2, 3, 4];
+        let selected = HashSet::from([1, 2, 4]);
+        assert_eq!(restack(&order, &selected, Restack::Forward), vec![0, 3, 1, 2, 4]);
+        assert_eq!(restack(&order, &selected, Restack::Backward), vec![1, 2, 0, 4, 3]);
+        assert_eq!(restack(&order, &selected, Restack::ToFront), vec![0, 3, 1, 2, 4]);
+        assert_eq!(restack(&order, &selected, Restack::ToBack), vec![1, 2, 4, 0, 3]);
+    }
+
+    #[test]
+    fn the_topmost_block_is_hit() {
+        let mut graph = Graph::new();
+        let lower = graph.add_block(Block::new_sized(Vector2::new(0., 0.), 100., 100.));
+        let upper = graph.add_block(Block::new_sized(Vector2::new(50., 50.), 100., 100.));
+        assert_eq!(graph.block_at(Vector2::new(75., 75.)), Some(upper));
+        graph.block_mut(lower).unwrap().z_index = 2;
+        assert_eq!(graph.block_at(Vector2::new(75., 75.)), Some(lower));
+        assert_eq!(graph.z_order(), vec![upper, lower]);
+    }
+
+    #[test]
+    fn raise_on_click_is_undone_with_the_drag() {
+        let mut context = Context::new();
+        context.raise_on_click = true;
+        for position in [Vector2::new(10., 10.), Vector2::new(300., 300.)] {
+            context.mouse_position = position;
+            context.on_keydown("n".to_string());
+        }
+        let z_indices = |context: &Context| context.graph().blocks().map(|block| block.z_index).collect::<Vec<_>>();
+        assert_eq!(z_indices(&context), vec![0, 1]);
+        context.mouse_position = Vector2::new(20., 20.);
+        context.on_mouse_clicked(MouseButton::Left);
+        context.drag = true;
+        context.move_block(Vector2::new(60., 20.));
+        context.end_drag();
+        assert_eq!(z_indices(&context), vec![2, 1]);
+        context.undo();
+        assert_eq!(z_indices(&context), vec![0, 1]);
+        assert_eq!(context.graph().block(0).unwrap().pos, Vector2::new(10., 10.));
+        context.undo();
+        assert_eq!(context.graph().blocks().count(), 1);
+        // The block on top isn't raised again
+        context.mouse_position = Vector2::new(20., 20.);
+        context.on_mouse_clicked(MouseButton::Left);
+        context.drag = true;
+        context.end_drag();
+        context.undo();
+        assert_eq!(context.graph().blocks().count(), 0);
+    }
+
+    #[test]
+    fn the_ports_under_a_block_are_hidden() {
+        let mut graph = Graph::new();
+        let mut block = Block::new_sized(Vector2::new(0., 0.), 100., 100.);
+        block.outputs.push(Port::any());
+        let lower = graph.add_block(block);
+        let pos = Vector2::new(100., 50.);
+        assert_eq!(graph.port_at(pos, 5.), Some((lower, PortRef::new(PortSide::Output, 0))));
+        let upper = graph.add_block(Block::new_sized(Vector2::new(50., 0.), 100., 100.));
+        assert_eq!(graph.port_at(pos, 5.), None);
+        graph.block_mut(upper).unwrap().z_index = 0;
+        graph.block_mut(lower).unwrap().z_index = 1;
+        assert_eq!(graph.port_at(pos, 5.), Some((lower, PortRef::new(PortSide::Output, 0))));
+    }
+}